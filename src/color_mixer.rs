@@ -1,23 +1,334 @@
+#[cfg(feature = "server")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "random")]
+use rand::Rng;
 use rgb::RGB8;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+#[cfg(feature = "persistence")]
+use std::fs;
+#[cfg(feature = "persistence")]
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
+use crate::css_colors;
 use crate::error::{ColorMixerError, Result};
 
-/// Maximum number of colors that can be mixed
+/// Default maximum number of colors that can be mixed
 const MAX_COLORS: usize = 1000;
 
-/// Color representation that stores RGB values
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Number of changes kept in the history, oldest dropped first
+const HISTORY_LIMIT: usize = 1000;
+
+/// Number of operations that can be undone unless configured, oldest dropped first
+const UNDO_LIMIT: usize = 100;
+
+/// Default maximum number of units a single add can bring in
+const MAX_PER_REQUEST: u32 = 100;
+
+/// How far each channel may be off a custom shade for a color to still count as that shade's family
+const CLASSIFY_TOLERANCE: u8 = 3;
+
+/// Number of distinct colors from which mixing spreads its per-color work over threads
+#[cfg(feature = "parallel")]
+pub const PARALLEL_THRESHOLD: usize = 4096;
+
+/// Number of color families
+const FAMILY_COUNT: usize = 4;
+
+/// The custom shades of every color family, by name
+const SHADES: [(&str, (u8, u8, u8)); 12] = [
+    // Yellow shades
+    ("yellow", (255, 237, 0)),
+    ("light-yellow", (255, 249, 128)),
+    ("dark-yellow", (204, 187, 0)),
+    // Blue shades
+    ("blue", (0, 71, 171)),
+    ("light-blue", (102, 153, 255)),
+    ("dark-blue", (0, 32, 91)),
+    // Red shades
+    ("red", (255, 0, 0)),
+    ("light-red", (255, 128, 128)),
+    ("dark-red", (204, 0, 0)),
+    // Green shades
+    ("green", (0, 255, 0)),
+    ("light-green", (128, 255, 128)),
+    ("dark-green", (0, 204, 0)),
+];
+
+/// Everyday names for the custom shades, as `(alias, shade)`. They are looked up after the shades but
+/// before the CSS colors, so `navy` and `gold` name the dark blue and dark yellow shades.
+const SHADE_ALIASES: [(&str, &str); 8] = [
+    ("lemon", "light-yellow"),
+    ("gold", "dark-yellow"),
+    ("sky", "light-blue"),
+    ("navy", "dark-blue"),
+    ("rose", "light-red"),
+    ("ruby", "dark-red"),
+    ("mint", "light-green"),
+    ("forest", "dark-green"),
+];
+
+/// Relative luminance above which a color counts as light
+const LIGHT_LUMINANCE_THRESHOLD: f64 = 0.5;
+
+/// Channel spread (largest minus smallest channel, 0-1) below which a color reads as neutral gray
+const NEUTRAL_CHROMA: f32 = 0.1;
+
+/// Hue in degrees where the warm reds begin, going round through 0°
+const WARM_HUE_START: f32 = 330.0;
+
+/// Hue in degrees where the warm yellows end and the cool yellow-greens begin
+const WARM_HUE_END: f32 = 75.0;
+
+/// Fewest colors in a gradient, its two endpoints
+const MIN_GRADIENT_STEPS: usize = 2;
+
+/// Most colors in a gradient, to keep responses small
+const MAX_GRADIENT_STEPS: usize = 256;
+
+/// Fewest steps in a tint or shade ramp, reaching white or black in one go
+const MIN_RAMP_STEPS: usize = 1;
+
+/// Coolest temperature `Color::from_kelvin` approximates, in kelvin
+const MIN_KELVIN: f32 = 1000.0;
+
+/// Hottest temperature `Color::from_kelvin` approximates, in kelvin
+const MAX_KELVIN: f32 = 40000.0;
+
+/// Opacity of a fully opaque color
+const OPAQUE: u8 = 255;
+
+/// Distance from a whole number within which a mixed channel counts as that number. Converting
+/// to linear light and back can land a hair off, e.g. 236.99999999999997 for 237, which would
+/// otherwise floor to the next value down.
+const ROUNDING_EPSILON: f64 = 1e-9;
+
+/// Added to every channel before a subtractive mix, so no pigment absorbs a channel completely
+/// and a small share of a color only tints the mix rather than wiping out its channels
+const PIGMENT_OFFSET: f64 = 64.0;
+
+/// Linear RGB to LMS cone responses, as used by Viénot, Brettel and Mollon (1999)
+const RGB_TO_LMS: [[f64; 3]; 3] = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+
+/// Inverse of `RGB_TO_LMS`
+const LMS_TO_RGB: [[f64; 3]; 3] = [
+    [0.0809444479, -0.130504409, 0.116721066],
+    [-0.0102485335, 0.0540193266, -0.113614708],
+    [-0.000365296938, -0.00412161469, 0.693511405],
+];
+
+/// Linear sRGB to CIE XYZ under the D65 white point, scaled so white has Y = 100
+const RGB_TO_XYZ: [[f64; 3]; 3] = [
+    [41.24564, 35.75761, 18.04375],
+    [21.26729, 71.51522, 7.21750],
+    [1.93339, 11.91920, 95.03041],
+];
+
+/// CIE XYZ of the D65 white point, the reference white of `Color::to_lab`
+const D65_WHITE: [f64; 3] = [95.047, 100.0, 108.883];
+
+/// A family of custom shades
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    Yellow,
+    Blue,
+    Red,
+    Green,
+}
+
+/// Every family, in the order `SHADES` lists them
+const FAMILIES: [Family; FAMILY_COUNT] = [Family::Yellow, Family::Blue, Family::Red, Family::Green];
+
+/// The shades of a family, in the order `SHADES` lists them
+const SHADE_ORDER: [Shade; 3] = [Shade::Standard, Shade::Light, Shade::Dark];
+
+impl Family {
+    /// The family's color in the given shade
+    pub fn color(self, shade: Shade) -> Color {
+        let offset = SHADE_ORDER.iter().position(|known| *known == shade).unwrap_or_default();
+        let (_, (r, g, b)) = SHADES[self as usize * SHADE_ORDER.len() + offset];
+        Color::new(r, g, b)
+    }
+}
+
+/// The family and shade a color exactly matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorKind {
+    pub family: Family,
+    pub shade: Shade,
+}
+
+impl FromStr for Family {
+    type Err = ColorMixerError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "yellow" => Ok(Family::Yellow),
+            "blue" => Ok(Family::Blue),
+            "red" => Ok(Family::Red),
+            "green" => Ok(Family::Green),
+            _ => Err(ColorMixerError::UnsupportedColor(format!(
+                "Unsupported color family: {}. Please use one of yellow, blue, red or green.",
+                s
+            ))),
+        }
+    }
+}
+
+/// How light or dark a color of a family is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Shade {
+    Light,
+    #[default]
+    Standard,
+    Dark,
+}
+
+/// Color harmony used to build a palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HarmonyScheme {
+    /// The color and its complement
+    Complement,
+    /// The color and the two colors 120° away
+    Triadic,
+    /// The color and its neighbours 30° away
+    Analogous,
+}
+
+/// Whether a color reads as warm, cool or neither
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Temperature {
+    /// Reds, oranges and yellows
+    Warm,
+    /// Greens, blues and purples
+    Cool,
+    /// Grays, too little color to lean either way
+    Neutral,
+}
+
+/// Whether a ramp runs towards white or black
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum RampDirection {
+    /// Lighter variants, mixed towards white
+    Tint,
+    /// Darker variants, mixed towards black
+    Shade,
+}
+
+/// Color vision deficiency a color can be simulated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum CvdKind {
+    /// Missing long-wavelength (red) cones
+    Protanopia,
+    /// Missing medium-wavelength (green) cones
+    Deuteranopia,
+    /// Missing short-wavelength (blue) cones
+    Tritanopia,
+}
+
+impl CvdKind {
+    /// Projection rebuilding the missing cone response from the other two. The planes keep
+    /// white and pure blue unchanged, or for tritanopia white and pure red.
+    fn lms_projection(self) -> [[f64; 3]; 3] {
+        match self {
+            CvdKind::Protanopia => [[0.0, 2.02344, -2.52581], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            CvdKind::Deuteranopia => [[1.0, 0.0, 0.0], [0.494207, 0.0, 1.24827], [0.0, 0.0, 1.0]],
+            CvdKind::Tritanopia => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-0.0122450, 0.0720345, 0.0]],
+        }
+    }
+}
+
+/// Single-color filter, as used for image effects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Every channel flipped, like a photographic negative
+    Invert,
+    /// The gray of the same luma
+    Grayscale,
+    /// Brownish tone of an old photograph
+    Sepia,
+}
+
+/// File format for exporting the mixer's colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// CSS custom properties on `:root`
+    Css,
+    /// GIMP palette, also read by Inkscape and Krita
+    Gpl,
+    /// `hex,r,g,b,count,a` rows for spreadsheets
+    Csv,
+    /// JSON array like `GET /api/colors`
+    #[default]
+    Json,
+}
+
+/// Adjustment of a color's lightness or saturation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Adjustment {
+    Lighten,
+    Darken,
+    Saturate,
+    Desaturate,
+}
+
+/// Photoshop-style blend operation applied per channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Multiply the channels, darkening the result
+    Multiply,
+    /// Invert, multiply and invert again, lightening the result
+    Screen,
+    /// Multiply dark base channels and screen light ones, boosting contrast
+    Overlay,
+}
+
+/// Color representation that stores RGB values and an alpha channel.
+/// Serializes as `#RRGGBB`, or `#RRGGBBAA` when not fully opaque.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Color {
     rgb: RGB8,
+    alpha: u8,
 }
 
 impl Color {
-    /// Create a new color from RGB values
+    /// Create a new opaque color from RGB values
     pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self::with_alpha(r, g, b, OPAQUE)
+    }
+
+    /// Create a new color from RGB values and an alpha channel
+    pub fn with_alpha(r: u8, g: u8, b: u8, alpha: u8) -> Self {
         Self {
             rgb: RGB8::new(r, g, b),
+            alpha,
         }
     }
 
@@ -26,11 +337,429 @@ impl Color {
         (self.rgb.r, self.rgb.g, self.rgb.b)
     }
 
+    /// Get the alpha channel of the color (0 is transparent, 255 is opaque)
+    pub fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
     /// Get the hex representation of the color
     pub fn to_hex(&self) -> String {
         format!("#{:02X}{:02X}{:02X}", self.rgb.r, self.rgb.g, self.rgb.b)
     }
 
+    /// Get the hex representation of the color including alpha (`#RRGGBBAA`)
+    pub fn to_rgba_hex(&self) -> String {
+        format!("{}{:02X}", self.to_hex(), self.alpha)
+    }
+
+    /// Get the hue in degrees along with the largest and smallest channel normalized to 0..1
+    fn hue_components(&self) -> (f32, f32, f32) {
+        let r = self.rgb.r as f32 / 255.0;
+        let g = self.rgb.g as f32 / 255.0;
+        let b = self.rgb.b as f32 / 255.0;
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        // Hue is undefined for achromatic colors, report 0
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+
+        (hue, max, min)
+    }
+
+    /// Get the HSL representation: hue in degrees (0-360), saturation and lightness in percent (0-100)
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (hue, max, min) = self.hue_components();
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+
+        (hue, saturation * 100.0, lightness * 100.0)
+    }
+
+    /// Create an opaque color from a hue in degrees and saturation and lightness in percent
+    pub fn from_hsl(h: f32, s: f32, l: f32) -> Self {
+        let h = h.rem_euclid(360.0);
+        let s = (s / 100.0).clamp(0.0, 1.0);
+        let l = (l / 100.0).clamp(0.0, 1.0);
+
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = chroma * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+        let (r, g, b) = match (h / 60.0) as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let m = l - chroma / 2.0;
+        let [r, g, b] = [r, g, b].map(|channel| round_channel((channel + m) * 255.0));
+        Self::new(r, g, b)
+    }
+
+    /// Rotate the hue by the given number of degrees, keeping saturation, lightness and alpha.
+    /// Any angle works, negative or beyond a full turn; grays have no hue and stay unchanged.
+    pub fn rotate_hue(&self, degrees: f32) -> Color {
+        let (h, s, l) = self.to_hsl();
+        let (r, g, b) = Self::from_hsl(h + degrees, s, l).rgb();
+        Color::with_alpha(r, g, b, self.alpha)
+    }
+
+    /// Shift saturation and lightness by the given percentage points, clamped to 0-100
+    fn shift_hsl(&self, saturation: f32, lightness: f32) -> Color {
+        let (h, s, l) = self.to_hsl();
+        let (r, g, b) = Self::from_hsl(h, s + saturation, l + lightness).rgb();
+        Color::with_alpha(r, g, b, self.alpha)
+    }
+
+    /// Raise the HSL lightness by `pct` percentage points (0-100)
+    pub fn lighten(&self, pct: f32) -> Color {
+        self.shift_hsl(0.0, pct.clamp(0.0, 100.0))
+    }
+
+    /// Lower the HSL lightness by `pct` percentage points (0-100)
+    pub fn darken(&self, pct: f32) -> Color {
+        self.shift_hsl(0.0, -pct.clamp(0.0, 100.0))
+    }
+
+    /// Raise the HSL saturation by `pct` percentage points (0-100)
+    pub fn saturate(&self, pct: f32) -> Color {
+        self.shift_hsl(pct.clamp(0.0, 100.0), 0.0)
+    }
+
+    /// Lower the HSL saturation by `pct` percentage points (0-100)
+    pub fn desaturate(&self, pct: f32) -> Color {
+        self.shift_hsl(-pct.clamp(0.0, 100.0), 0.0)
+    }
+
+    /// Apply an adjustment by `pct` percentage points
+    pub fn adjust(&self, adjustment: Adjustment, pct: f32) -> Color {
+        match adjustment {
+            Adjustment::Lighten => self.lighten(pct),
+            Adjustment::Darken => self.darken(pct),
+            Adjustment::Saturate => self.saturate(pct),
+            Adjustment::Desaturate => self.desaturate(pct),
+        }
+    }
+
+    /// Get the complementary color, opposite on the color wheel
+    pub fn complement(&self) -> Color {
+        self.rotate_hue(180.0)
+    }
+
+    /// Get the triadic palette: this color and the two colors 120° away
+    pub fn triadic(&self) -> [Color; 3] {
+        [self.clone(), self.rotate_hue(120.0), self.rotate_hue(240.0)]
+    }
+
+    /// Get the analogous palette: this color between its neighbours 30° away on either side
+    pub fn analogous(&self) -> [Color; 3] {
+        [self.rotate_hue(-30.0), self.clone(), self.rotate_hue(30.0)]
+    }
+
+    /// Build the palette for a harmony scheme, starting from this color
+    pub fn harmony(&self, scheme: HarmonyScheme) -> Vec<Color> {
+        match scheme {
+            HarmonyScheme::Complement => vec![self.clone(), self.complement()],
+            HarmonyScheme::Triadic => self.triadic().to_vec(),
+            HarmonyScheme::Analogous => self.analogous().to_vec(),
+        }
+    }
+
+    /// Flip every channel (255 minus the channel), keeping alpha
+    pub fn invert(&self) -> Color {
+        Color::with_alpha(255 - self.rgb.r, 255 - self.rgb.g, 255 - self.rgb.b, self.alpha)
+    }
+
+    /// The gray with the same Rec. 601 luma, keeping alpha
+    pub fn grayscale(&self) -> Color {
+        let luma = 0.299 * self.rgb.r as f32 + 0.587 * self.rgb.g as f32 + 0.114 * self.rgb.b as f32;
+        let gray = round_channel(luma);
+        Color::with_alpha(gray, gray, gray, self.alpha)
+    }
+
+    /// Apply the standard sepia tone matrix, clamping channels that overflow, keeping alpha
+    pub fn sepia(&self) -> Color {
+        let (r, g, b) = (self.rgb.r as f32, self.rgb.g as f32, self.rgb.b as f32);
+        Color::with_alpha(
+            round_channel(0.393 * r + 0.769 * g + 0.189 * b),
+            round_channel(0.349 * r + 0.686 * g + 0.168 * b),
+            round_channel(0.272 * r + 0.534 * g + 0.131 * b),
+            self.alpha,
+        )
+    }
+
+    /// Apply a filter to this color
+    pub fn filter(&self, filter: Filter) -> Color {
+        match filter {
+            Filter::Invert => self.invert(),
+            Filter::Grayscale => self.grayscale(),
+            Filter::Sepia => self.sepia(),
+        }
+    }
+
+    /// Get the HSV representation: hue in degrees (0-360), saturation and value in percent (0-100)
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let (hue, max, min) = self.hue_components();
+        let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+        (hue, saturation * 100.0, max * 100.0)
+    }
+
+    /// Get the CMYK representation with every component in 0..1
+    pub fn to_cmyk(&self) -> (f32, f32, f32, f32) {
+        let r = self.rgb.r as f32 / 255.0;
+        let g = self.rgb.g as f32 / 255.0;
+        let b = self.rgb.b as f32 / 255.0;
+
+        let k = 1.0 - r.max(g).max(b);
+        // Pure black has no ink left to distribute over C, M and Y
+        if k >= 1.0 {
+            return (0.0, 0.0, 0.0, 1.0);
+        }
+
+        let c = (1.0 - r - k) / (1.0 - k);
+        let m = (1.0 - g - k) / (1.0 - k);
+        let y = (1.0 - b - k) / (1.0 - k);
+        (c, m, y, k)
+    }
+
+    /// Create an opaque color from CMYK components in 0..1, clamping out of range values
+    pub fn from_cmyk(c: f32, m: f32, y: f32, k: f32) -> Self {
+        let k = k.clamp(0.0, 1.0);
+        let [r, g, b] = [c, m, y].map(|ink| round_channel(255.0 * (1.0 - ink.clamp(0.0, 1.0)) * (1.0 - k)));
+        Self::new(r, g, b)
+    }
+
+    /// Create an opaque color from the light of a blackbody at `kelvin`, clamped to 1000-40000 K.
+    /// Uses Tanner Helland's curve fit: warm orange at 2000 K, near neutral white at 6500 K and
+    /// cool blue above that.
+    pub fn from_kelvin(kelvin: f32) -> Self {
+        let t = kelvin.clamp(MIN_KELVIN, MAX_KELVIN) as f64 / 100.0;
+        let r = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+        let g = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+
+        let [r, g, b] = [r, g, b].map(|channel| round_channel(channel as f32));
+        Self::new(r, g, b)
+    }
+
+    /// Get the WCAG relative luminance, from 0 (black) to 1 (white)
+    pub fn relative_luminance(&self) -> f64 {
+        0.2126 * srgb_to_linear(self.rgb.r) + 0.7152 * srgb_to_linear(self.rgb.g) + 0.0722 * srgb_to_linear(self.rgb.b)
+    }
+
+    /// Classify the color as warm, cool or neutral by its hue, ignoring alpha
+    pub fn temperature(&self) -> Temperature {
+        let (hue, max, min) = self.hue_components();
+        if max - min < NEUTRAL_CHROMA {
+            Temperature::Neutral
+        } else if !(WARM_HUE_END..WARM_HUE_START).contains(&hue) {
+            Temperature::Warm
+        } else {
+            Temperature::Cool
+        }
+    }
+
+    /// Whether the color is light enough that dark text reads better on top of it
+    pub fn is_light(&self) -> bool {
+        self.relative_luminance() > LIGHT_LUMINANCE_THRESHOLD
+    }
+
+    /// Get the WCAG contrast ratio against another color, from 1 (identical) to 21 (black on white)
+    pub fn contrast_ratio(&self, other: &Color) -> f64 {
+        let (first, second) = (self.relative_luminance(), other.relative_luminance());
+        let (lighter, darker) = if first >= second { (first, second) } else { (second, first) };
+        (lighter + 0.05) / (darker + 0.05)
+    }
+
+    /// Linearly interpolate each channel towards another color, with `t` clamped to 0..1
+    pub fn lerp(&self, other: &Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |from: u8, to: u8| round_channel(from as f32 + (to as f32 - from as f32) * t);
+        Color::with_alpha(
+            mix(self.rgb.r, other.rgb.r),
+            mix(self.rgb.g, other.rgb.g),
+            mix(self.rgb.b, other.rgb.b),
+            mix(self.alpha, other.alpha),
+        )
+    }
+
+    /// Build an evenly spaced gradient of `steps` colors towards another color, including both ends
+    pub fn gradient(&self, other: &Color, steps: usize) -> Result<Vec<Color>> {
+        if !(MIN_GRADIENT_STEPS..=MAX_GRADIENT_STEPS).contains(&steps) {
+            return Err(ColorMixerError::InvalidSteps {
+                min: MIN_GRADIENT_STEPS,
+                max: MAX_GRADIENT_STEPS,
+            });
+        }
+
+        let last = (steps - 1) as f32;
+        Ok((0..steps).map(|step| self.lerp(other, step as f32 / last)).collect())
+    }
+
+    /// Build a ramp of `steps` variants mixed ever further towards `target`, after this color itself,
+    /// keeping this color's alpha
+    fn ramp(&self, target: (u8, u8, u8), steps: usize) -> Result<Vec<Color>> {
+        if !(MIN_RAMP_STEPS..=MAX_GRADIENT_STEPS).contains(&steps) {
+            return Err(ColorMixerError::InvalidSteps {
+                min: MIN_RAMP_STEPS,
+                max: MAX_GRADIENT_STEPS,
+            });
+        }
+
+        let target = Color::with_alpha(target.0, target.1, target.2, self.alpha);
+        Ok((0..=steps).map(|step| self.lerp(&target, step as f32 / steps as f32)).collect())
+    }
+
+    /// Build a shade ramp: this color followed by `steps` ever darker variants, ending in black
+    pub fn shades(&self, steps: usize) -> Result<Vec<Color>> {
+        self.ramp((0, 0, 0), steps)
+    }
+
+    /// Build a tint ramp: this color followed by `steps` ever lighter variants, ending in white
+    pub fn tints(&self, steps: usize) -> Result<Vec<Color>> {
+        self.ramp((255, 255, 255), steps)
+    }
+
+    /// Build the tint or shade ramp of this color
+    pub fn ramp_towards(&self, direction: RampDirection, steps: usize) -> Result<Vec<Color>> {
+        match direction {
+            RampDirection::Tint => self.tints(steps),
+            RampDirection::Shade => self.shades(steps),
+        }
+    }
+
+    /// Blend another color on top of this one, keeping this color's alpha
+    pub fn blend(&self, other: &Color, mode: BlendMode) -> Color {
+        let blend_channel = |base: u8, top: u8| {
+            let base = base as f32 / 255.0;
+            let top = top as f32 / 255.0;
+            let blended = match mode {
+                BlendMode::Multiply => base * top,
+                BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - top),
+                BlendMode::Overlay if base < 0.5 => 2.0 * base * top,
+                BlendMode::Overlay => 1.0 - 2.0 * (1.0 - base) * (1.0 - top),
+            };
+            round_channel(blended * 255.0)
+        };
+
+        Color::with_alpha(
+            blend_channel(self.rgb.r, other.rgb.r),
+            blend_channel(self.rgb.g, other.rgb.g),
+            blend_channel(self.rgb.b, other.rgb.b),
+            self.alpha,
+        )
+    }
+
+    /// Simulate how the color looks to someone with the given color vision deficiency,
+    /// by dropping a cone response in LMS space. Alpha is kept.
+    pub fn simulate_cvd(&self, kind: CvdKind) -> Color {
+        let linear = [
+            srgb_to_linear(self.rgb.r),
+            srgb_to_linear(self.rgb.g),
+            srgb_to_linear(self.rgb.b),
+        ];
+        let lms = transform(&kind.lms_projection(), transform(&RGB_TO_LMS, linear));
+        let [r, g, b] = transform(&LMS_TO_RGB, lms).map(|channel| {
+            round_channel((linear_to_srgb(channel.clamp(0.0, 1.0)) * 255.0) as f32)
+        });
+
+        Color::with_alpha(r, g, b, self.alpha)
+    }
+
+    /// Every named color, custom shades first so they win over CSS colors with the same value
+    fn named_colors() -> impl Iterator<Item = &'static (&'static str, (u8, u8, u8))> {
+        SHADES.iter().chain(css_colors::CSS_COLORS.iter())
+    }
+
+    /// Get the name of the color if it exactly matches a known shade or CSS color
+    pub fn name(&self) -> Option<&'static str> {
+        Self::named_colors()
+            .find(|(_, rgb)| *rgb == self.rgb())
+            .map(|(name, _)| *name)
+    }
+
+    /// Get the name of the closest known shade or CSS color by Delta-E
+    pub fn nearest_name(&self) -> &'static str {
+        let lab = self.to_lab();
+        let distance = |&(r, g, b): &(u8, u8, u8)| lab_distance(lab, Color::new(r, g, b).to_lab());
+
+        // min_by keeps the first of equally close entries, so custom shades win ties
+        Self::named_colors()
+            .min_by(|(_, first), (_, second)| distance(first).total_cmp(&distance(second)))
+            .map(|(name, _)| *name)
+            .expect("the palette is never empty")
+    }
+
+    /// Euclidean distance to another color in RGB space, ignoring alpha
+    pub fn distance(&self, other: &Color) -> f64 {
+        let (r, g, b) = self.rgb();
+        let (other_r, other_g, other_b) = other.rgb();
+        [(r, other_r), (g, other_g), (b, other_b)]
+            .iter()
+            .map(|&(a, b)| (a as f64 - b as f64).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// Get the CIE XYZ coordinates under the D65 white point, with Y from 0 to 100
+    pub fn to_xyz(&self) -> (f64, f64, f64) {
+        let linear = [self.rgb.r, self.rgb.g, self.rgb.b].map(srgb_to_linear);
+        let [x, y, z] = transform(&RGB_TO_XYZ, linear);
+        (x, y, z)
+    }
+
+    /// Get the CIELAB coordinates relative to D65 white: lightness from 0 to 100 and the
+    /// green-red and blue-yellow axes `a` and `b`
+    pub fn to_lab(&self) -> (f64, f64, f64) {
+        let (x, y, z) = self.to_xyz();
+        let [white_x, white_y, white_z] = D65_WHITE;
+        let (fx, fy, fz) = (lab_f(x / white_x), lab_f(y / white_y), lab_f(z / white_z));
+        (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+
+    /// Get the CIE76 color difference to another color: the distance between the two in
+    /// CIELAB space, ignoring alpha. Around 2.3 is the smallest difference most people notice.
+    pub fn delta_e(&self, other: &Color) -> f64 {
+        lab_distance(self.to_lab(), other.to_lab())
+    }
+
+    /// Get the closest of the custom shades by Delta-E, keeping this color's alpha
+    pub fn snap_to_palette(&self) -> Color {
+        let (r, g, b) = SHADES
+            .iter()
+            .map(|&(_, (r, g, b))| Color::new(r, g, b))
+            .min_by(|a, b| self.delta_e(a).total_cmp(&self.delta_e(b)))
+            .map(|shade| shade.rgb())
+            .expect("the palette is never empty");
+        Color::with_alpha(r, g, b, self.alpha)
+    }
+
     /// Compare color to standard yellow
     pub fn is_yellow(&self) -> bool {
         self.rgb.r == 255 && self.rgb.g == 237 && self.rgb.b == 0
@@ -60,41 +789,240 @@ impl Color {
     pub fn is_dark_blue(&self) -> bool {
         self.rgb.r == 0 && self.rgb.g == 32 && self.rgb.b == 91
     }
+
+    /// Compare color to standard red
+    pub fn is_red(&self) -> bool {
+        self.rgb.r == 255 && self.rgb.g == 0 && self.rgb.b == 0
+    }
+
+    /// Compare color to light red
+    pub fn is_light_red(&self) -> bool {
+        self.rgb.r == 255 && self.rgb.g == 128 && self.rgb.b == 128
+    }
+
+    /// Compare color to dark red
+    pub fn is_dark_red(&self) -> bool {
+        self.rgb.r == 204 && self.rgb.g == 0 && self.rgb.b == 0
+    }
+
+    /// Compare color to standard green
+    pub fn is_green(&self) -> bool {
+        self.rgb.r == 0 && self.rgb.g == 255 && self.rgb.b == 0
+    }
+
+    /// Compare color to light green
+    pub fn is_light_green(&self) -> bool {
+        self.rgb.r == 128 && self.rgb.g == 255 && self.rgb.b == 128
+    }
+
+    /// Compare color to dark green
+    pub fn is_dark_green(&self) -> bool {
+        self.rgb.r == 0 && self.rgb.g == 204 && self.rgb.b == 0
+    }
+
+    /// The family and shade this color is, if it is exactly one of the custom shades
+    pub fn kind(&self) -> Option<ColorKind> {
+        self.kind_near(0)
+    }
+
+    /// The family and shade of the closest custom shade whose channels each differ from this
+    /// color's by at most `tolerance`, if any
+    pub fn kind_near(&self, tolerance: u8) -> Option<ColorKind> {
+        let (index, _) = SHADES
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, (r, g, b)))| (index, self.channel_distance(&Color::new(r, g, b))))
+            .filter(|&(_, distance)| distance <= tolerance)
+            .min_by_key(|&(_, distance)| distance)?;
+        Some(ColorKind {
+            family: FAMILIES[index / SHADE_ORDER.len()],
+            shade: SHADE_ORDER[index % SHADE_ORDER.len()],
+        })
+    }
+
+    /// Whether every channel differs from `other`'s by at most `tolerance`; alpha is ignored
+    pub fn is_near(&self, other: &Color, tolerance: u8) -> bool {
+        self.channel_distance(other) <= tolerance
+    }
+
+    /// Largest difference between a channel of this color and the same channel of `other`
+    fn channel_distance(&self, other: &Color) -> u8 {
+        let (r, g, b) = self.rgb();
+        let (other_r, other_g, other_b) = other.rgb();
+        r.abs_diff(other_r).max(g.abs_diff(other_g)).max(b.abs_diff(other_b))
+    }
+}
+
+impl Color {
+    /// Resolve a color name in a shade into a color. Any color can be used in the standard
+    /// shade, the light and dark ones only exist for the families.
+    pub fn from_shade(color_str: &str, shade: Shade) -> Result<Self> {
+        if shade == Shade::Standard {
+            return Self::from_str(color_str);
+        }
+
+        let family = Family::from_str(color_str).map_err(|_| {
+            ColorMixerError::UnsupportedShade(format!(
+                "Unsupported shade for {}. Light and dark shades exist only for yellow, blue, red and green.",
+                color_str
+            ))
+        })?;
+        Ok(family.color(shade))
+    }
+
+    /// Parse a `#RRGGBB` or shorthand `#RGB` hex color; the `#` is optional and names are not accepted
+    pub fn from_hex(s: &str) -> Result<Self> {
+        Self::parse_hex(s.strip_prefix('#').unwrap_or(s))
+            .ok_or_else(|| ColorMixerError::InvalidHex(format!("Invalid hex color: {}. Expected #RRGGBB or #RGB.", s)))
+    }
+
+    /// Parse the digits of a `RRGGBB` or shorthand `RGB` hex string (without the leading `#`)
+    fn parse_hex(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        // Expand shorthand by doubling each nibble (`abc` -> `aabbcc`)
+        let hex = match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect(),
+            6 => hex.to_string(),
+            _ => return None,
+        };
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
 }
 
 impl FromStr for Color {
     type Err = ColorMixerError;
 
     fn from_str(s: &str) -> Result<Self> {
-        match s.to_lowercase().as_str() {
-            // Yellow shades
-            "yellow" | "#ffed00" => Ok(Self::new(255, 237, 0)),
-            "light-yellow" | "#fff980" => Ok(Self::new(255, 249, 128)),
-            "dark-yellow" | "#ccbb00" => Ok(Self::new(204, 187, 0)),
+        // Without the `#`, short hex strings like `add` would be mistaken for names
+        let s = s.trim();
+        if s.starts_with('#') {
+            return Self::from_hex(s);
+        }
+
+        // `light blue`, `light_blue` and `Light-Blue` all name the same shade
+        let name = s
+            .split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+            .to_lowercase();
+        let shade = |name: &str| SHADES.iter().find(|(known, _)| *known == name).map(|&(_, rgb)| rgb);
+        if let Some((r, g, b)) = shade(&name) {
+            return Ok(Self::new(r, g, b));
+        }
+
+        let alias = SHADE_ALIASES.iter().find(|(alias, _)| *alias == name);
+        if let Some((r, g, b)) = alias.and_then(|(_, name)| shade(name)) {
+            return Ok(Self::new(r, g, b));
+        }
+
+        // Standard web colors, after the custom shades and their aliases so those take precedence
+        if let Some((r, g, b)) = css_colors::lookup(&name) {
+            return Ok(Self::new(r, g, b));
+        }
+
+        // Hex digits missing their `#`: show the color they would be and the closest shade
+        if let Some(color) = Self::parse_hex(s) {
+            let nearest = color.snap_to_palette().name().expect("every shade has a name");
+            return Err(ColorMixerError::UnsupportedColor(format!(
+                "Unsupported color: {} (hex values need a leading #), did you mean '{}'?",
+                color.to_hex(),
+                nearest
+            )));
+        }
+        Err(ColorMixerError::UnsupportedColor(
+            format!("Unsupported color: {}. Please use one of the predefined yellow, blue, red or green shades, a CSS color name or a hex value.", s)
+        ))
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.alpha {
+            OPAQUE => serializer.serialize_str(&self.to_hex()),
+            _ => serializer.serialize_str(&self.to_rgba_hex()),
+        }
+    }
+}
 
-            // Blue shades
-            "blue" | "#0047ab" => Ok(Self::new(0, 71, 171)),
-            "light-blue" | "#6699ff" => Ok(Self::new(102, 153, 255)),
-            "dark-blue" | "#00205b" => Ok(Self::new(0, 32, 91)),
+impl<'de> Deserialize<'de> for Color {
+    /// Accepts the forms of `from_hex` and `#RRGGBBAA`, as well as the `{ "rgb": ..., "alpha": ... }`
+    /// objects of state files saved before colors were written as hex
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Hex(String),
+            Channels { rgb: RGB8, alpha: u8 },
+        }
 
-            _ => Err(ColorMixerError::UnsupportedColor(
-                format!("Unsupported color: {}. Please use one of the predefined yellow or blue shades.", s)
-            )),
+        match Repr::deserialize(deserializer)? {
+            Repr::Hex(hex) => {
+                let digits = hex.strip_prefix('#').unwrap_or(&hex);
+                if digits.len() == 8 && digits.is_ascii() {
+                    let (rgb, alpha) = digits.split_at(6);
+                    let alpha =
+                        u8::from_str_radix(alpha, 16).map_err(|_| de::Error::custom("invalid alpha in hex color"))?;
+                    let color = Self::from_hex(rgb).map_err(de::Error::custom)?;
+                    let (r, g, b) = color.rgb();
+                    return Ok(Self::with_alpha(r, g, b, alpha));
+                }
+                Self::from_hex(&hex).map_err(de::Error::custom)
+            }
+            Repr::Channels { rgb, alpha } => Ok(Self { rgb, alpha }),
         }
     }
 }
 
+impl fmt::Display for Color {
+    /// Formats as `#RRGGBB`, like `to_hex`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl Ord for Color {
+    /// Orders by red, then green, then blue; alpha only breaks ties so the order agrees with `Eq`
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.rgb(), self.alpha).cmp(&(other.rgb(), other.alpha))
+    }
+}
+
+impl PartialOrd for Color {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// Request for adding a color to the mixer
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
 pub struct AddColorRequest {
-    /// The color to add ("yellow" or "blue")
+    /// The color to add: a family, shade or CSS color name, or a hex value
+    #[serde(default)]
     pub color: String,
+    /// The color to add as red, green and blue channels, instead of `color`
+    pub rgb: Option<[u8; 3]>,
+    /// The color to add as cyan, magenta, yellow and black in 0-1, instead of `color`
+    pub cmyk: Option<[f32; 4]>,
+    /// The color to add as hue in degrees and saturation and lightness in percent, instead of `color`
+    pub hsl: Option<[f32; 3]>,
+    /// The color to add as the light of a blackbody at this temperature in kelvin, instead of `color`
+    pub kelvin: Option<f32>,
     /// The shade of the color ("light", "standard", or "dark")
-    #[serde(default = "default_shade")]
-    pub shade: String,
+    #[serde(default)]
+    pub shade: Shade,
     /// The quantity of the color to add (default: 1)
     #[serde(default = "default_quantity")]
     pub quantity: u32,
+    /// The opacity of the color, 0 (transparent) to 255 (opaque, default)
+    #[serde(default = "default_alpha")]
+    pub alpha: u8,
 }
 
 /// Default quantity for color addition
@@ -102,161 +1030,2399 @@ fn default_quantity() -> u32 {
     1
 }
 
-/// Default shade for color addition
-fn default_shade() -> String {
-    "standard".to_string()
-}
+impl AddColorRequest {
+    /// Whether a color, rgb channels, cmyk components, hsl values or a temperature were given at all
+    pub fn names_color(&self) -> bool {
+        !self.color.is_empty() || self.has_values()
+    }
 
-/// Color mixer with RGB support
-#[derive(Debug, Clone)]
-pub struct ColorMixer {
-    /// List of colors currently in the mixer
-    colors: Vec<Color>,
-    /// Maximum number of colors allowed
-    max_colors: usize,
-}
+    /// Whether the color was given as numbers rather than by name or hex value
+    fn has_values(&self) -> bool {
+        self.rgb.is_some() || self.cmyk.is_some() || self.hsl.is_some() || self.kelvin.is_some()
+    }
+
+    /// Resolve the requested color, channels, components or temperature, shade and alpha into a color
+    pub fn to_color(&self) -> Result<Color> {
+        let given = [
+            !self.color.is_empty(),
+            self.rgb.is_some(),
+            self.cmyk.is_some(),
+            self.hsl.is_some(),
+            self.kelvin.is_some(),
+        ];
+        if given.iter().filter(|&&given| given).count() > 1 {
+            return Err(ColorMixerError::InvalidBody(
+                "Give only one of a color, rgb channels, cmyk components, hsl values or kelvin".to_string(),
+            ));
+        }
+        if self.has_values() && self.shade != Shade::Standard {
+            return Err(ColorMixerError::UnsupportedShade(
+                "Unsupported shade for numeric colors. Light and dark shades exist only for yellow, blue, red and green."
+                    .to_string(),
+            ));
+        }
+
+        let (r, g, b) = match (self.rgb, self.cmyk, self.hsl, self.kelvin) {
+            (Some([r, g, b]), _, _, _) => (r, g, b),
+            (_, Some(cmyk), _, _) => {
+                if let Some(&component) = cmyk.iter().find(|component| !(0.0..=1.0).contains(*component)) {
+                    return Err(ColorMixerError::InvalidCmyk(component));
+                }
+                let [c, m, y, k] = cmyk;
+                Color::from_cmyk(c, m, y, k).rgb()
+            }
+            (_, _, Some(hsl @ [h, s, l]), _) => {
+                if !(0.0..360.0).contains(&h) || !(0.0..=100.0).contains(&s) || !(0.0..=100.0).contains(&l) {
+                    return Err(ColorMixerError::InvalidHsl(hsl));
+                }
+                Color::from_hsl(h, s, l).rgb()
+            }
+            (_, _, _, Some(kelvin)) => Color::from_kelvin(kelvin).rgb(),
+            (None, None, None, None) => Color::from_shade(&self.color, self.shade)?.rgb(),
+        };
+        Ok(Color::with_alpha(r, g, b, self.alpha))
+    }
+}
+
+/// Options for adding a color to the mixer
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct AddColorQuery {
+    /// Snap the color to the closest custom shade before adding it
+    #[serde(default)]
+    pub snap: bool,
+    /// Mix mode to report the result with, instead of the mixer's own
+    pub mode: Option<MixMode>,
+}
+
+/// Options for reading the mixed color
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct MixQuery {
+    /// Mix mode to use instead of the mixer's own
+    pub mode: Option<MixMode>,
+    /// Rounding of the mixed channels to use instead of the mixer's own
+    pub rounding: Option<RoundingMode>,
+}
+
+/// Request for the contrast ratio between two colors
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct ContrastRequest {
+    /// The text color, as a name or hex value
+    pub foreground: String,
+    /// The background color, as a name or hex value
+    pub background: String,
+}
+
+/// Request for the perceptual difference between two colors
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct DeltaERequest {
+    /// The color compared against, as a name or hex value
+    pub reference: String,
+    /// The color compared, as a name or hex value
+    pub sample: String,
+}
+
+/// Options for building a palette
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct PaletteQuery {
+    /// The harmony scheme to use
+    pub scheme: HarmonyScheme,
+}
+
+/// Options for exporting the colors
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct ExportQuery {
+    /// The file format, JSON when omitted
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Options for importing colors from CSV
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct ImportQuery {
+    /// Add the imported colors to the current ones instead of replacing them
+    #[serde(default)]
+    pub append: bool,
+}
+
+/// Options for random colors
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct RandomQuery {
+    /// Seed for reproducible colors; fresh ones every time when omitted
+    pub seed: Option<u64>,
+    /// Number of colors to add (default: 1)
+    pub count: Option<u32>,
+}
+
+/// Options for sampling one of the colors
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct SampleQuery {
+    /// Seed for a reproducible pick; a fresh one every time when omitted
+    pub seed: Option<u64>,
+}
+
+/// Options for filtering a color
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct FilterQuery {
+    /// The filter to apply
+    pub name: Filter,
+}
+
+/// Options for the tint or shade ramp of the mixed color
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct RampQuery {
+    /// Towards white (`tint`) or black (`shade`)
+    pub direction: RampDirection,
+    /// Number of variants after the mixed color, the last being white or black
+    pub steps: usize,
+}
+
+/// Options for simulating a color vision deficiency
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct CvdQuery {
+    /// The deficiency to simulate
+    pub kind: CvdKind,
+}
+
+/// Options for the PNG swatch of the mixed color
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct PngQuery {
+    /// Width and height in pixels
+    pub size: Option<u32>,
+}
+
+/// Options for the SVG swatch of the mixed color
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct SvgQuery {
+    /// Width in pixels
+    pub width: Option<u32>,
+    /// Height in pixels
+    pub height: Option<u32>,
+}
+
+/// Request for a gradient between two colors
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct GradientRequest {
+    /// The first color, as a name or hex value
+    pub from: String,
+    /// The last color, as a name or hex value
+    pub to: String,
+    /// Number of colors in the gradient, including both endpoints
+    pub steps: usize,
+}
+
+/// Request for adjusting the mixed color
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct AdjustRequest {
+    /// The adjustment ("lighten", "darken", "saturate" or "desaturate")
+    pub op: Adjustment,
+    /// Percentage points to adjust by, clamped to 0-100
+    pub amount: f32,
+}
+
+/// Request for rotating the hue of the mixed color
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct RotateRequest {
+    /// Degrees to rotate by, wrapping around the color wheel
+    pub degrees: f32,
+}
+
+/// Request for blending one color on top of another
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct BlendRequest {
+    /// The bottom color, as a name or hex value
+    pub base: String,
+    /// The color blended on top, as a name or hex value
+    pub top: String,
+    /// The blend operation ("multiply", "screen" or "overlay")
+    pub mode: BlendMode,
+}
+
+/// Request for mixing a list of colors without touching the shared mixer
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct MixRequest {
+    /// The colors to mix, in the same shape as a single addition
+    pub colors: Vec<AddColorRequest>,
+}
+
+/// Round a mixed channel half-up to the nearest valid `u8`
+fn round_channel(value: f32) -> u8 {
+    (value + 0.5).clamp(0.0, 255.0) as u8
+}
+
+/// Default alpha for color addition
+fn default_alpha() -> u8 {
+    OPAQUE
+}
+
+/// How a mixed channel is turned back into a whole `u8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    /// Round down
+    Floor,
+    /// Round to the nearest value, halves up
+    #[default]
+    Round,
+    /// Round up
+    Ceil,
+}
+
+impl RoundingMode {
+    /// Turn a channel value into a valid `u8` in this direction; values within `ROUNDING_EPSILON`
+    /// of a whole number are that number in every direction
+    fn apply(self, value: f64) -> u8 {
+        let nearest = value.round();
+        let value = match self {
+            _ if (value - nearest).abs() < ROUNDING_EPSILON => nearest,
+            RoundingMode::Floor => value.floor(),
+            RoundingMode::Round => (value + 0.5).floor(),
+            RoundingMode::Ceil => value.ceil(),
+        };
+        value.clamp(0.0, 255.0) as u8
+    }
+
+    /// Divide a channel sum by its weight and round the quotient.
+    /// Whole-number sums and weights stay exact in f64, so unit counts round exactly.
+    fn divide(self, sum: f64, weight: f64) -> u8 {
+        self.apply(sum / weight)
+    }
+}
+
+/// Strategy used to combine the colors in the mixer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum MixMode {
+    /// Light-like mixing: weighted average of the family shades, counting colors near a shade as that shade
+    Additive,
+    /// Paint-like mixing: weighted geometric mean of the channel reflectances, each kept above zero
+    #[default]
+    Subtractive,
+    /// Gamma-correct mixing: weighted average of the channels in linear light
+    LinearAverage,
+    /// Straight weighted average of every unit's RGB channels, with no grouping by family
+    DirectAverage,
+}
+
+/// Number of units in each color family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct FamilyCounts {
+    pub yellow: u64,
+    pub blue: u64,
+    pub red: u64,
+    pub green: u64,
+}
+
+/// Per-channel statistics over every color unit in the mixer
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct ColorStats {
+    /// Mean of the red, green and blue channels
+    pub mean: (f64, f64, f64),
+    /// Population standard deviation of the red, green and blue channels
+    pub std_dev: (f64, f64, f64),
+    /// Number of distinct colors
+    pub distinct: usize,
+}
+
+/// Share of the units in each color family, in percent rounded to one decimal so the shares add up to 100
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct Composition {
+    pub yellow_pct: f64,
+    pub blue_pct: f64,
+    pub red_pct: f64,
+    pub green_pct: f64,
+    /// Share of the colors outside the four families
+    pub other_pct: f64,
+}
+
+/// Kind of change listed in a mixer's history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryOp {
+    /// Units of a color added
+    Add,
+    /// Units of a color removed
+    Remove,
+    /// The count of a color set directly
+    Set,
+    /// Every color removed
+    Clear,
+    /// The whole contents replaced
+    Replace,
+    /// The most recent change undone
+    Undo,
+    /// The most recently undone change applied again
+    Redo,
+}
+
+/// A change to the mixer and when it happened
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct HistoryEntry {
+    /// What changed
+    pub op: HistoryOp,
+    /// The color added or removed
+    #[cfg_attr(feature = "docs", schema(value_type = Option<String>))]
+    pub color: Option<Color>,
+    /// Units added, removed, set or cleared, or held afterwards for a replacement
+    pub quantity: Option<u32>,
+    /// When the change happened. Only the `server` feature brings in a clock, so targets without
+    /// one, such as WASM, keep the history untimed.
+    #[cfg(feature = "server")]
+    pub at: DateTime<Utc>,
+}
+
+/// A change to the colors, holding only the units it changes. Applying one gives back the
+/// change that reverses it, which is what undo and redo keep.
+#[derive(Debug, Clone)]
+enum Operation {
+    /// Units added to the entries at these positions, ascending and counted after the change;
+    /// a color not at its position yet is inserted there
+    Added(Vec<(usize, Color, u32)>),
+    /// Units taken from the entries at these positions, ascending and counted before the change;
+    /// entries left without units are dropped
+    Removed(Vec<(usize, Color, u32)>),
+    /// The whole contents replaced by these color counts
+    Replaced(Vec<(Color, u32)>),
+}
+
+/// Color mixer with RGB support
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorMixer {
+    /// Each distinct color with its number of units, in order of first insertion.
+    /// Only a handful of distinct colors are expected, so a list beats a hash map.
+    colors: Vec<(Color, u32)>,
+    /// Maximum number of colors allowed
+    max_colors: usize,
+    /// Maximum number of units a single add can bring in; missing from files saved before the limit existed
+    #[serde(default = "default_max_per_request")]
+    max_per_request: u32,
+    /// Maximum number of units of any one color, unlimited when `None`
+    #[serde(default)]
+    max_per_color: Option<u32>,
+    /// How colors are combined when mixing
+    mix_mode: MixMode,
+    /// How mixed channels are rounded to whole values
+    #[serde(default)]
+    rounding: RoundingMode,
+    /// Named copies of the color counts, in order of creation
+    #[serde(default)]
+    snapshots: Vec<(String, Vec<(Color, u32)>)>,
+    /// Maximum number of operations kept for undo
+    #[serde(default = "default_max_undo")]
+    max_undo: usize,
+    /// How to reverse the last `max_undo` changes, most recent last; not persisted
+    #[serde(skip)]
+    history: VecDeque<Operation>,
+    /// How to apply the undone changes again, most recently undone last; not persisted
+    #[serde(skip)]
+    redo: Vec<Operation>,
+    /// The last `HISTORY_LIMIT` changes with their times, oldest first; not persisted
+    #[serde(skip)]
+    log: VecDeque<HistoryEntry>,
+    /// The mix in the mixer's own mode and rounding, kept until the colors or settings change
+    #[serde(skip)]
+    cached: OnceLock<Color>,
+}
 
 impl ColorMixer {
     /// Create a new color mixer
     pub fn new() -> Self {
+        Self::with_max_colors(MAX_COLORS)
+    }
+
+    /// Create a new color mixer with room for `capacity` distinct colors before reallocating
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut mixer = Self::new();
+        mixer.colors.reserve_exact(capacity);
+        mixer
+    }
+
+    /// Create a new color mixer that holds at most `max` colors
+    pub fn with_max_colors(max: usize) -> Self {
         Self {
             colors: Vec::new(),
-            max_colors: MAX_COLORS,
+            max_colors: max,
+            max_per_request: MAX_PER_REQUEST,
+            max_per_color: None,
+            mix_mode: MixMode::default(),
+            rounding: RoundingMode::default(),
+            snapshots: Vec::new(),
+            max_undo: UNDO_LIMIT,
+            history: VecDeque::new(),
+            redo: Vec::new(),
+            log: VecDeque::new(),
+            cached: OnceLock::new(),
         }
     }
 
-    /// Add multiple units of a color at once
-    pub fn add_colors_str(&mut self, color_str: &str, shade: &str, quantity: u32) -> Result<()> {
-        // Construct the full color name with shade
-        let full_color_name = if shade == "standard" {
-            color_str.to_string()
-        } else {
-            format!("{}-{}", shade, color_str)
-        };
+    /// Load a mixer previously written by `save_to_path`
+    #[cfg(feature = "persistence")]
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|err| ColorMixerError::Persistence(format!("Failed to read {}: {err}", path.display())))?;
+        let mixer: Self = serde_json::from_str(&json)
+            .map_err(|err| ColorMixerError::Persistence(format!("Failed to parse {}: {err}", path.display())))?;
+
+        let current = mixer.len();
+        if current > mixer.max_colors {
+            return Err(ColorMixerError::MaxColorsBelowCount {
+                max: mixer.max_colors,
+                current,
+            });
+        }
+
+        Ok(mixer)
+    }
+
+    /// Write the colors, limits, mix mode and snapshots to `path` as JSON; the undo and redo history is not saved.
+    /// The file is replaced atomically so a crash mid-write never leaves it half written.
+    #[cfg(feature = "persistence")]
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|err| ColorMixerError::Persistence(format!("Failed to serialize state: {err}")))?;
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, json)
+            .and_then(|_| fs::rename(&temp, path))
+            .map_err(|err| ColorMixerError::Persistence(format!("Failed to write {}: {err}", path.display())))
+    }
+
+    /// Get the maximum number of colors allowed
+    pub fn max_colors(&self) -> usize {
+        self.max_colors
+    }
+
+    /// Change the maximum number of colors, which can't be lower than the current count
+    pub fn set_max_colors(&mut self, max: usize) -> Result<()> {
+        let current = self.len();
+        if max < current {
+            return Err(ColorMixerError::MaxColorsBelowCount { max, current });
+        }
+
+        self.max_colors = max;
+        Ok(())
+    }
+
+    /// Get the maximum number of units a single add can bring in
+    pub fn max_per_request(&self) -> u32 {
+        self.max_per_request
+    }
+
+    /// Change the maximum number of units a single add can bring in
+    pub fn set_max_per_request(&mut self, max: u32) {
+        self.max_per_request = max;
+    }
+
+    /// Get the maximum number of units of any one color, `None` when unlimited
+    pub fn max_per_color(&self) -> Option<u32> {
+        self.max_per_color
+    }
+
+    /// Get the maximum number of operations that can be undone
+    pub fn max_undo(&self) -> usize {
+        self.max_undo
+    }
+
+    /// Change the maximum number of operations that can be undone, forgetting the oldest beyond it;
+    /// zero turns undo off. The undone operations that can be redone are limited the same way.
+    pub fn set_max_undo(&mut self, max: usize) {
+        self.max_undo = max;
+        self.history.drain(..self.history.len().saturating_sub(max));
+        self.redo.drain(..self.redo.len().saturating_sub(max));
+    }
+
+    /// Change the maximum number of units of any one color, or lift it with `None`. This applies on top of
+    /// `max_colors`; colors already over a new limit are kept, but no more units of them can be added.
+    pub fn set_max_per_color(&mut self, max: Option<u32>) {
+        self.max_per_color = max;
+    }
+
+    /// Check that `count` units of `color` stay within the per-color limit
+    fn check_color_limit(&self, color: &Color, count: u64) -> Result<()> {
+        match self.max_per_color {
+            Some(limit) if count > limit as u64 => Err(ColorMixerError::ColorQuantityExceeded {
+                color: color.to_string(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Units of exactly `color`, alpha included
+    fn count_of(&self, color: &Color) -> u32 {
+        self.colors.iter().find(|(known, _)| known == color).map_or(0, |(_, count)| *count)
+    }
+
+    /// Get the current mix mode
+    pub fn mix_mode(&self) -> MixMode {
+        self.mix_mode
+    }
+
+    /// Change how colors are combined when mixing
+    pub fn set_mix_mode(&mut self, mode: MixMode) {
+        self.mix_mode = mode;
+        self.cached.take();
+    }
+
+    /// Get how mixed channels are rounded
+    pub fn rounding(&self) -> RoundingMode {
+        self.rounding
+    }
+
+    /// Change how mixed channels are rounded
+    pub fn set_rounding(&mut self, rounding: RoundingMode) {
+        self.rounding = rounding;
+        self.cached.take();
+    }
+
+    /// Total number of color units in the mixer, summed over the distinct colors only
+    pub fn len(&self) -> usize {
+        count_units(&self.colors)
+    }
+
+    /// Whether the mixer holds no colors
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
 
+    /// Number of units in each color family, allowing colors a few units off a shade;
+    /// colors outside the families are not counted
+    pub fn family_counts(&self) -> FamilyCounts {
+        // Added in u64, as a family can hold several colors of up to u32::MAX units each
+        let mut counts = [0u64; FAMILY_COUNT];
+        for (color, count) in &self.colors {
+            if let Some(kind) = color.kind_near(CLASSIFY_TOLERANCE) {
+                counts[kind.family as usize] += u64::from(*count);
+            }
+        }
+
+        let [yellow, blue, red, green] = counts;
+        FamilyCounts { yellow, blue, red, green }
+    }
+
+    /// Add multiple units of a color with the given opacity at once
+    pub fn add_colors_str(&mut self, color_str: &str, shade: Shade, quantity: u32, alpha: u8) -> Result<()> {
         // Validate the color first to avoid partial additions if the color is invalid
-        let color = Color::from_str(&full_color_name)?;
+        let (r, g, b) = Color::from_shade(color_str, shade)?.rgb();
+        self.add_colors(Color::with_alpha(r, g, b, alpha), quantity)
+    }
+
+    /// Add multiple opaque units of a color given by its channels
+    pub fn add_rgb(&mut self, r: u8, g: u8, b: u8, quantity: u32) -> Result<()> {
+        self.add_colors(Color::new(r, g, b), quantity)
+    }
+
+    /// Add multiple opaque units of a family's shade at once
+    pub fn add_family(&mut self, family: Family, shade: Shade, quantity: u32) -> Result<()> {
+        self.add_colors(family.color(shade), quantity)
+    }
+
+    /// Add multiple units of a color at once; adding none fails, as it does in `add_batch`
+    pub fn add_colors(&mut self, color: Color, quantity: u32) -> Result<()> {
+        if quantity == 0 {
+            return Err(ColorMixerError::ZeroQuantity);
+        }
+        if quantity > self.max_per_request {
+            return Err(ColorMixerError::QuantityTooLarge(self.max_per_request));
+        }
 
         // Check if we have enough space for all colors
-        let current_count = self.colors.len();
+        let current_count = self.len();
         let quantity_usize = quantity as usize;
 
         if current_count + quantity_usize > self.max_colors {
             return Err(ColorMixerError::MaxColorsReached);
         }
+        self.check_color_limit(&color, self.count_of(&color) as u64 + quantity as u64)?;
 
         // Add the colors
-        for _ in 0..quantity {
-            self.colors.push(color.clone());
+        let addition = self.addition([(color.clone(), quantity)]);
+        self.commit(addition);
+        self.log(HistoryOp::Add, Some(color), Some(quantity));
+
+        Ok(())
+    }
+
+    /// Add several colors at once, all or nothing.
+    ///
+    /// Every entry is checked against the per-add limit, the combined total against the
+    /// maximum and each color's new count against the per-color limit before anything
+    /// changes; the error says which entry was rejected. Undoing removes the whole batch.
+    pub fn add_batch(&mut self, colors: Vec<(Color, u32)>) -> Result<()> {
+        for (index, (_, quantity)) in colors.iter().enumerate() {
+            let error = match *quantity {
+                0 => ColorMixerError::ZeroQuantity,
+                quantity if quantity > self.max_per_request => ColorMixerError::QuantityTooLarge(self.max_per_request),
+                _ => continue,
+            };
+            return Err(ColorMixerError::InvalidEntry {
+                index,
+                source: Box::new(error),
+            });
+        }
+
+        let added: usize = colors.iter().map(|(_, quantity)| *quantity as usize).sum();
+        if self.len() + added > self.max_colors {
+            return Err(ColorMixerError::MaxColorsReached);
+        }
+        if self.max_per_color.is_some() {
+            for (index, (color, _)) in colors.iter().enumerate() {
+                // Entries may repeat a color, so count it up to this entry and report the one going over
+                let same = colors[..=index].iter().filter(|(other, _)| other == color);
+                let batched: u64 = same.map(|(_, quantity)| *quantity as u64).sum();
+                self.check_color_limit(color, self.count_of(color) as u64 + batched)
+                    .map_err(|source| ColorMixerError::InvalidEntry {
+                        index,
+                        source: Box::new(source),
+                    })?;
+            }
+        }
+        if colors.is_empty() {
+            return Ok(());
         }
 
+        let addition = self.addition(colors.iter().cloned());
+        self.commit(addition);
+        for (color, quantity) in colors {
+            self.log(HistoryOp::Add, Some(color), Some(quantity));
+        }
         Ok(())
     }
 
-    /// Clear all colors from the mixer
-    pub fn clear(&mut self) {
-        self.colors.clear();
+    /// Make the count of exactly `color`, alpha included, equal to `quantity`; zero removes it.
+    /// Fails without changing anything if the new total or count is over its limit. Can be undone,
+    /// unless the count was already `quantity` and nothing changed.
+    pub fn set_quantity(&mut self, color: &Color, quantity: u32) -> Result<()> {
+        let current = self.count_of(color);
+        if self.len() - current as usize + quantity as usize > self.max_colors {
+            return Err(ColorMixerError::MaxColorsReached);
+        }
+        self.check_color_limit(color, quantity as u64)?;
+
+        let operation = match self.colors.iter().position(|(known, _)| known == color) {
+            _ if quantity == current => return Ok(()),
+            Some(index) if quantity < current => Operation::Removed(vec![(index, color.clone(), current - quantity)]),
+            _ => self.addition([(color.clone(), quantity - current)]),
+        };
+        self.commit(operation);
+        self.log(HistoryOp::Set, Some(color.clone()), Some(quantity));
+        Ok(())
     }
 
-    /// Get the currently mixed color
-    pub fn get_mixed_color(&self) -> Result<Color> {
-        if self.colors.is_empty() {
-            return Err(ColorMixerError::NoColors);
+    /// Replace the whole contents with units of the named colors, given as `(color, shade, quantity)`.
+    ///
+    /// Every entry is checked before anything changes, so on error the mixer is left untouched
+    /// and the error says which entry was rejected.
+    pub fn set_colors(&mut self, colors: Vec<(String, Shade, u32)>) -> Result<()> {
+        let colors = colors
+            .iter()
+            .enumerate()
+            .map(|(index, (color_str, shade, quantity))| {
+                let color = match quantity {
+                    0 => Err(ColorMixerError::ZeroQuantity),
+                    _ => Color::from_shade(color_str, *shade),
+                };
+                color
+                    .map(|color| (color, *quantity))
+                    .map_err(|source| ColorMixerError::InvalidEntry {
+                        index,
+                        source: Box::new(source),
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.replace_colors(colors)
+    }
+
+    /// Replace the whole contents with the given color units, merging repeated colors.
+    /// Fails without changing anything if the total or any color's count is over its limit.
+    pub fn replace_colors(&mut self, colors: Vec<(Color, u32)>) -> Result<()> {
+        let mut merged: Vec<(Color, u32)> = Vec::with_capacity(colors.len());
+        for (color, quantity) in colors.into_iter().filter(|(_, quantity)| *quantity > 0) {
+            match merged.iter_mut().find(|(known, _)| *known == color) {
+                Some((_, count)) => *count += quantity,
+                None => merged.push((color, quantity)),
+            }
+        }
+
+        if count_units(&merged) > self.max_colors {
+            return Err(ColorMixerError::MaxColorsReached);
+        }
+        for (color, count) in &merged {
+            self.check_color_limit(color, *count as u64)?;
+        }
+
+        self.commit(Operation::Replaced(merged));
+        self.log(HistoryOp::Replace, None, Some(self.len() as u32));
+        Ok(())
+    }
+
+    /// Remove up to `quantity` units of a color and return how many were removed.
+    ///
+    /// Units match on RGB regardless of their alpha. When several opacities of the color are
+    /// present, those first added most recently are removed first. Removing zero units changes
+    /// nothing and leaves nothing to undo.
+    pub fn remove_colors_str(&mut self, color_str: &str, shade: Shade, quantity: u32) -> Result<u32> {
+        let rgb = Color::from_shade(color_str, shade)?.rgb();
+        self.remove_rgb(rgb, color_str, quantity)
+    }
+
+    /// Remove up to `quantity` units of a color, matching on RGB like `remove_colors_str`
+    pub fn remove_colors(&mut self, color: &Color, quantity: u32) -> Result<u32> {
+        self.remove_rgb(color.rgb(), &color.to_hex(), quantity)
+    }
+
+    /// Remove units whose channels are `rgb`, naming the color `label` if there are none
+    fn remove_rgb(&mut self, rgb: (u8, u8, u8), label: &str, quantity: u32) -> Result<u32> {
+        let present: u32 = self
+            .colors
+            .iter()
+            .filter(|(color, _)| color.rgb() == rgb)
+            .map(|(_, count)| count)
+            .sum();
+        if present == 0 {
+            return Err(ColorMixerError::ColorNotFound(format!(
+                "Color not found: {}. Add it before removing it.",
+                label
+            )));
+        }
+
+        // Removing nothing changes nothing, so there is nothing to undo or log
+        let removed = present.min(quantity);
+        if removed == 0 {
+            return Ok(0);
         }
 
-        // If only one color, return it directly
-        if self.colors.len() == 1 {
-            return Ok(self.colors[0].clone());
-        }
-
-        // Count the number of each color shade
-        let mut std_yellow_count = 0;
-        let mut light_yellow_count = 0;
-        let mut dark_yellow_count = 0;
-        let mut std_blue_count = 0;
-        let mut light_blue_count = 0;
-        let mut dark_blue_count = 0;
-
-        for color in &self.colors {
-            if color.is_yellow() {
-                std_yellow_count += 1;
-            } else if color.is_light_yellow() {
-                light_yellow_count += 1;
-            } else if color.is_dark_yellow() {
-                dark_yellow_count += 1;
-            } else if color.is_blue() {
-                std_blue_count += 1;
-            } else if color.is_light_blue() {
-                light_blue_count += 1;
-            } else if color.is_dark_blue() {
-                dark_blue_count += 1;
+        // Take units from the newest matching entries and keep the oldest ones
+        let mut remaining = removed;
+        let mut taken = Vec::new();
+        for (index, (color, count)) in self.colors.iter().enumerate().rev() {
+            if color.rgb() == rgb && remaining > 0 {
+                let units = remaining.min(*count);
+                taken.push((index, color.clone(), units));
+                remaining -= units;
             }
         }
+        taken.reverse();
+        self.commit(Operation::Removed(taken));
+        self.log(HistoryOp::Remove, Some(Color::new(rgb.0, rgb.1, rgb.2)), Some(removed));
+
+        Ok(removed)
+    }
+
+    /// Iterate over every unit in the mixer. Units of the same color come one after another,
+    /// and the colors in order of first insertion.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            colors: self.colors.iter(),
+            current: None,
+        }
+    }
+
+    /// Get each distinct color with the number of units present, in order of first insertion
+    pub fn color_counts(&self) -> Vec<(Color, u32)> {
+        self.colors.clone()
+    }
+
+    /// Get each distinct color once, sorted by channel; every entry of the mixer is already distinct,
+    /// so this copies one color per entry rather than per unit
+    pub fn distinct_colors(&self) -> Vec<Color> {
+        let mut colors: Vec<Color> = self.colors.iter().map(|(color, _)| color.clone()).collect();
+        colors.sort_unstable();
+        colors
+    }
+
+    /// Mean and standard deviation of each channel over all units, zero when the mixer is empty
+    pub fn stats(&self) -> ColorStats {
+        let units = self.len() as f64;
+        if units == 0.0 {
+            return ColorStats::default();
+        }
+
+        let channels = |color: &Color| {
+            let (r, g, b) = color.rgb();
+            [r as f64, g as f64, b as f64]
+        };
+        // Sum over the distinct colors weighted by their counts
+        let weighted_sum = |value: &dyn Fn(&Color) -> [f64; 3]| {
+            self.colors.iter().fold([0.0; 3], |sum, (color, count)| {
+                let value = value(color);
+                [0, 1, 2].map(|i| sum[i] + value[i] * *count as f64)
+            })
+        };
+
+        let mean = weighted_sum(&channels).map(|sum| sum / units);
+        let variance = weighted_sum(&|color| {
+            let value = channels(color);
+            [0, 1, 2].map(|i| (value[i] - mean[i]).powi(2))
+        })
+        .map(|sum| sum / units);
+        let std_dev = variance.map(f64::sqrt);
+
+        ColorStats {
+            mean: (mean[0], mean[1], mean[2]),
+            std_dev: (std_dev[0], std_dev[1], std_dev[2]),
+            distinct: self.colors.len(),
+        }
+    }
+
+    /// Share of each family in the units the mix is computed from, counted as in `family_counts`;
+    /// all zero when the mixer is empty. The shares are rounded by largest remainder, so they always
+    /// add up to exactly 100.
+    pub fn composition(&self) -> Composition {
+        let units = self.len() as u64;
+        if units == 0 {
+            return Composition::default();
+        }
+
+        let FamilyCounts { yellow, blue, red, green } = self.family_counts();
+        let other = units - (yellow + blue + red + green);
+        let counts = [yellow, blue, red, green, other];
+        // Whole tenths of a percent rounded down, then the tenths left over go to the largest remainders
+        let mut tenths = counts.map(|count| count * 1000 / units);
+        let mut by_remainder: Vec<usize> = (0..counts.len()).collect();
+        by_remainder.sort_by_key(|&i| std::cmp::Reverse(counts[i] * 1000 % units));
+        let left = 1000 - tenths.iter().sum::<u64>();
+        for &i in &by_remainder[..left as usize] {
+            tenths[i] += 1;
+        }
+
+        let [yellow_pct, blue_pct, red_pct, green_pct, other_pct] = tenths.map(|share| share as f64 / 10.0);
+        Composition { yellow_pct, blue_pct, red_pct, green_pct, other_pct }
+    }
+
+    /// Clear all colors from the mixer and return how many units were removed
+    pub fn clear(&mut self) -> usize {
+        let removed = self.len();
+        if !self.is_empty() {
+            self.commit(Operation::Replaced(Vec::new()));
+            self.log(HistoryOp::Clear, None, Some(removed as u32));
+        }
+        removed
+    }
+
+    /// Remove every color of `family`, in any shade, keeping the rest, and return the number of
+    /// units removed. Colors count as the family's within the tolerance used by `family_counts`.
+    /// Can be undone.
+    pub fn clear_family(&mut self, family: Family) -> usize {
+        let in_family = |color: &Color| color.kind_near(CLASSIFY_TOLERANCE).is_some_and(|kind| kind.family == family);
+        let taken: Vec<(usize, Color, u32)> = self
+            .colors
+            .iter()
+            .enumerate()
+            .filter(|(_, (color, _))| in_family(color))
+            .map(|(index, (color, count))| (index, color.clone(), *count))
+            .collect();
+        let removed: usize = taken.iter().map(|(_, _, count)| *count as usize).sum();
+        if removed > 0 {
+            self.commit(Operation::Removed(taken));
+            self.log(HistoryOp::Remove, None, Some(removed as u32));
+        }
+        removed
+    }
 
-        // Calculate total counts for each color family
-        let yellow_count = std_yellow_count + light_yellow_count + dark_yellow_count;
-        let blue_count = std_blue_count + light_blue_count + dark_blue_count;
-        let total = yellow_count + blue_count;
+    /// Add a change to the timestamped history, dropping the oldest beyond `HISTORY_LIMIT`
+    fn log(&mut self, op: HistoryOp, color: Option<Color>, quantity: Option<u32>) {
+        if self.log.len() == HISTORY_LIMIT {
+            self.log.pop_front();
+        }
+        self.log.push_back(HistoryEntry {
+            op,
+            color,
+            quantity,
+            #[cfg(feature = "server")]
+            at: Utc::now(),
+        });
+    }
 
-        // If there's only one color family, calculate the average of that family's shades
-        if yellow_count > 0 && blue_count == 0 {
-            // Only yellow shades
-            let r_sum = (255 * std_yellow_count) + (255 * light_yellow_count) + (204 * dark_yellow_count);
-            let g_sum = (237 * std_yellow_count) + (249 * light_yellow_count) + (187 * dark_yellow_count);
-            let b_sum = (0 * std_yellow_count) + (128 * light_yellow_count) + (0 * dark_yellow_count);
+    /// The most recent changes with their times, oldest first
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.log.iter().cloned().collect()
+    }
 
-            let r = (r_sum / yellow_count) as u8;
-            let g = (g_sum / yellow_count) as u8;
-            let b = (b_sum / yellow_count) as u8;
+    /// The operation adding `units` to the mixer, merging repeated colors. Colors not in the mixer
+    /// yet go after the others, in the order they first appear.
+    fn addition(&self, units: impl IntoIterator<Item = (Color, u32)>) -> Operation {
+        let mut added: Vec<(usize, Color, u32)> = Vec::new();
+        let mut new_colors = 0;
+        for (color, quantity) in units {
+            if let Some((_, _, total)) = added.iter_mut().find(|(_, known, _)| *known == color) {
+                *total += quantity;
+                continue;
+            }
+            let index = self.colors.iter().position(|(known, _)| *known == color).unwrap_or_else(|| {
+                new_colors += 1;
+                self.colors.len() + new_colors - 1
+            });
+            added.push((index, color, quantity));
+        }
+        added.sort_unstable_by_key(|(index, _, _)| *index);
+        Operation::Added(added)
+    }
 
-            return Ok(Color::new(r, g, b));
-        } else if blue_count > 0 && yellow_count == 0 {
-            // Only blue shades
-            let r_sum = (0 * std_blue_count) + (102 * light_blue_count) + (0 * dark_blue_count);
-            let g_sum = (71 * std_blue_count) + (153 * light_blue_count) + (32 * dark_blue_count);
-            let b_sum = (171 * std_blue_count) + (255 * light_blue_count) + (91 * dark_blue_count);
+    /// Change the colors as `operation` says and return the operation that reverses it.
+    /// Every change to the colors ends here, so this also drops the cached mix.
+    fn apply(&mut self, operation: Operation) -> Operation {
+        self.cached.take();
+        match operation {
+            Operation::Added(units) => {
+                for (index, color, quantity) in &units {
+                    match self.colors.get_mut(*index) {
+                        Some((known, count)) if known == color => *count += quantity,
+                        _ => self.colors.insert(*index, (color.clone(), *quantity)),
+                    }
+                }
+                Operation::Removed(units)
+            }
+            Operation::Removed(units) => {
+                for (index, _, quantity) in &units {
+                    self.colors[*index].1 -= quantity;
+                }
+                self.colors.retain(|(_, count)| *count > 0);
+                Operation::Added(units)
+            }
+            Operation::Replaced(colors) => Operation::Replaced(std::mem::replace(&mut self.colors, colors)),
+        }
+    }
 
-            let r = (r_sum / blue_count) as u8;
-            let g = (g_sum / blue_count) as u8;
-            let b = (b_sum / blue_count) as u8;
+    /// Make a change and remember how to undo it; a new change makes the undone ones impossible to redo
+    fn commit(&mut self, operation: Operation) {
+        let undo = self.apply(operation);
+        self.push_undo(undo);
+        self.redo.clear();
+    }
 
-            return Ok(Color::new(r, g, b));
+    /// Keep an operation for undo, forgetting the oldest beyond `max_undo`
+    fn push_undo(&mut self, operation: Operation) {
+        self.history.push_back(operation);
+        if self.history.len() > self.max_undo {
+            self.history.pop_front();
         }
+    }
+
+    /// Reverse the most recent add, remove, clear or replacement
+    pub fn undo(&mut self) -> Result<()> {
+        let operation = self.history.pop_back().ok_or(ColorMixerError::NothingToUndo)?;
+        let redo = self.apply(operation);
+        self.redo.push(redo);
+        self.log(HistoryOp::Undo, None, None);
 
-        // Mix yellow and blue shades
-        let yellow_ratio = yellow_count as f32 / total as f32;
-        let blue_ratio = blue_count as f32 / total as f32;
+        Ok(())
+    }
 
-        // Calculate weighted average for each color family
-        let yellow_r = if yellow_count > 0 {
-            ((255.0 * std_yellow_count as f32) + (255.0 * light_yellow_count as f32) + (204.0 * dark_yellow_count as f32)) / yellow_count as f32
-        } else { 0.0 };
+    /// Apply the most recently undone operation again, failing as an add would if it no longer fits the
+    /// limits
+    pub fn redo(&mut self) -> Result<()> {
+        let operation = self.redo.last().ok_or(ColorMixerError::NothingToRedo)?;
+        // The limits may have been lowered since the undo
+        let total = match operation {
+            Operation::Added(units) => self.len() + units.iter().map(|(_, _, units)| *units as usize).sum::<usize>(),
+            Operation::Removed(_) => 0,
+            Operation::Replaced(colors) => count_units(colors),
+        };
+        if total > self.max_colors {
+            return Err(ColorMixerError::MaxColorsReached);
+        }
+        match operation {
+            // Additions hold each color once, so its new count is what it has plus the entry
+            Operation::Added(units) => {
+                for (_, color, units) in units {
+                    self.check_color_limit(color, self.count_of(color) as u64 + *units as u64)?;
+                }
+            }
+            Operation::Removed(_) => {}
+            Operation::Replaced(colors) => {
+                for (color, count) in colors {
+                    self.check_color_limit(color, *count as u64)?;
+                }
+            }
+        }
 
-        let yellow_g = if yellow_count > 0 {
-            ((237.0 * std_yellow_count as f32) + (249.0 * light_yellow_count as f32) + (187.0 * dark_yellow_count as f32)) / yellow_count as f32
-        } else { 0.0 };
+        let operation = self.redo.pop().expect("checked above");
+        let undo = self.apply(operation);
+        self.push_undo(undo);
+        self.log(HistoryOp::Redo, None, None);
+        Ok(())
+    }
 
-        let yellow_b = if yellow_count > 0 {
-            ((0.0 * std_yellow_count as f32) + (128.0 * light_yellow_count as f32) + (0.0 * dark_yellow_count as f32)) / yellow_count as f32
-        } else { 0.0 };
+    /// Replace the contents with one unit of each color in `default_palette`.
+    /// Unlike `clear` this leaves a demo mix to start from; it can be undone.
+    pub fn reset_to_default(&mut self) -> Result<()> {
+        self.replace_colors(default_palette().into_iter().map(|color| (color, 1)).collect())
+    }
 
-        let blue_r = if blue_count > 0 {
-            ((0.0 * std_blue_count as f32) + (102.0 * light_blue_count as f32) + (0.0 * dark_blue_count as f32)) / blue_count as f32
-        } else { 0.0 };
+    /// Save a copy of the current colors as `name`, replacing any earlier snapshot of that name
+    pub fn snapshot(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        let colors = self.colors.clone();
+        match self.snapshots.iter_mut().find(|(known, _)| *known == name) {
+            Some((_, saved)) => *saved = colors,
+            None => self.snapshots.push((name, colors)),
+        }
+    }
 
-        let blue_g = if blue_count > 0 {
-            ((71.0 * std_blue_count as f32) + (153.0 * light_blue_count as f32) + (32.0 * dark_blue_count as f32)) / blue_count as f32
-        } else { 0.0 };
+    /// Replace the current colors with the snapshot `name`; the snapshot is kept and the restore can be undone
+    pub fn restore(&mut self, name: &str) -> Result<()> {
+        let (_, colors) = self
+            .snapshots
+            .iter()
+            .find(|(known, _)| known == name)
+            .ok_or_else(|| ColorMixerError::SnapshotNotFound(name.to_string()))?;
+        self.replace_colors(colors.clone())
+    }
 
-        let blue_b = if blue_count > 0 {
-            ((171.0 * std_blue_count as f32) + (255.0 * light_blue_count as f32) + (91.0 * dark_blue_count as f32)) / blue_count as f32
-        } else { 0.0 };
+    /// Names of the saved snapshots, oldest first
+    pub fn list_snapshots(&self) -> Vec<&str> {
+        self.snapshots.iter().map(|(name, _)| name.as_str()).collect()
+    }
 
-        // Final color mixing
-        let r = (yellow_r * yellow_ratio + blue_r * blue_ratio) as u8;
-        let g = ((yellow_g * yellow_ratio) + (blue_g * blue_ratio)) as u8;
-        let b = ((yellow_b * yellow_ratio) + (blue_b * blue_ratio)) as u8;
+    /// Get the currently mixed color
+    pub fn get_mixed_color(&self) -> Result<Color> {
+        self.get_mixed_color_with(self.mix_mode)
+    }
+
+    /// Get the currently mixed color using a specific mix mode
+    pub fn get_mixed_color_with(&self, mode: MixMode) -> Result<Color> {
+        self.get_mixed_color_rounded(mode, self.rounding)
+    }
+
+    /// Get the currently mixed color using a specific mix mode and rounding.
+    /// Only the mixer's own mode and rounding are cached; other combinations are mixed afresh.
+    pub fn get_mixed_color_rounded(&self, mode: MixMode, rounding: RoundingMode) -> Result<Color> {
+        if mode != self.mix_mode || rounding != self.rounding {
+            return mix_counts_rounded(&self.colors, mode, rounding);
+        }
+        if let Some(color) = self.cached.get() {
+            return Ok(color.clone());
+        }
+
+        let color = mix_counts_rounded(&self.colors, mode, rounding)?;
+        Ok(self.cached.get_or_init(|| color).clone())
+    }
+
+    /// Pick one of the colors at random instead of mixing them, each unit equally likely, so a
+    /// color with three times the units is picked three times as often
+    #[cfg(feature = "random")]
+    pub fn sample(&self, rng: &mut impl Rng) -> Result<Color> {
+        let total = self.len();
+        if total == 0 {
+            return Err(ColorMixerError::NoColors);
+        }
+
+        let mut unit = rng.random_range(0..total);
+        for (color, count) in &self.colors {
+            match unit.checked_sub(*count as usize) {
+                Some(rest) => unit = rest,
+                None => return Ok(color.clone()),
+            }
+        }
+        unreachable!("the unit is below the total count")
+    }
+
+    /// Compute the mixed color as if `quantity` units of `color` were added, without adding them.
+    /// Fails exactly when `add_colors` would.
+    pub fn preview_with(&self, color: &Color, quantity: u32) -> Result<Color> {
+        if quantity == 0 {
+            return Err(ColorMixerError::ZeroQuantity);
+        }
+        if quantity > self.max_per_request {
+            return Err(ColorMixerError::QuantityTooLarge(self.max_per_request));
+        }
+        if self.len() + quantity as usize > self.max_colors {
+            return Err(ColorMixerError::MaxColorsReached);
+        }
+        self.check_color_limit(color, self.count_of(color) as u64 + quantity as u64)?;
+
+        let mut colors = self.colors.clone();
+        colors.push((color.clone(), quantity));
+        mix_counts_rounded(&colors, self.mix_mode, self.rounding)
+    }
+}
+
+/// Colors loaded by `ColorMixer::reset_to_default`: the six shades of yellow and blue, the
+/// families the mixer started out with
+pub fn default_palette() -> Vec<Color> {
+    [Family::Yellow, Family::Blue]
+        .into_iter()
+        .flat_map(|family| SHADE_ORDER.map(|shade| family.color(shade)))
+        .collect()
+}
+
+fn default_max_per_request() -> u32 {
+    MAX_PER_REQUEST
+}
+
+fn default_max_undo() -> usize {
+    UNDO_LIMIT
+}
+
+impl Default for ColorMixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<Color> for ColorMixer {
+    /// Collect one unit per color into a mixer with the default limit. Colors beyond the limit
+    /// are dropped, since collecting cannot fail; the collected units are not in the undo history.
+    fn from_iter<I: IntoIterator<Item = Color>>(iter: I) -> Self {
+        let mut mixer = Self::new();
+        for color in iter.into_iter().take(mixer.max_colors) {
+            match mixer.colors.iter_mut().find(|(known, _)| *known == color) {
+                Some((_, count)) => *count += 1,
+                None => mixer.colors.push((color, 1)),
+            }
+        }
+        mixer
+    }
+}
+
+/// Iterator over the units of a `ColorMixer`, returned by `ColorMixer::iter`
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    /// The distinct colors not started yet
+    colors: std::slice::Iter<'a, (Color, u32)>,
+    /// The color being repeated and how many of its units are left
+    current: Option<(&'a Color, u32)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Color;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((color, left)) = &mut self.current
+                && *left > 0
+            {
+                *left -= 1;
+                return Some(color);
+            }
+            let (color, count) = self.colors.next()?;
+            self.current = Some((color, *count));
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ColorMixer {
+    type Item = &'a Color;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Mixer that takes colors in fractional parts, e.g. 1.5 parts yellow to 0.5 parts blue.
+/// Unlike `ColorMixer` it has no limit and no undo.
+#[derive(Debug, Clone, Default)]
+pub struct WeightedMixer {
+    /// Each distinct color with its total weight, in order of first insertion
+    colors: Vec<(Color, f32)>,
+    /// How colors are combined when mixing
+    mix_mode: MixMode,
+}
+
+impl WeightedMixer {
+    /// Create an empty weighted mixer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the current mix mode
+    pub fn mix_mode(&self) -> MixMode {
+        self.mix_mode
+    }
+
+    /// Change how colors are combined when mixing
+    pub fn set_mix_mode(&mut self, mode: MixMode) {
+        self.mix_mode = mode;
+    }
+
+    /// Add `weight` parts of a color; the weight must be finite and non-negative
+    pub fn add_weighted(&mut self, color: Color, weight: f32) -> Result<()> {
+        if !is_valid_weight(weight) {
+            return Err(ColorMixerError::InvalidWeight(weight));
+        }
+
+        match self.colors.iter_mut().find(|(known, _)| *known == color) {
+            Some((_, total)) => *total += weight,
+            None => self.colors.push((color, weight)),
+        }
+        Ok(())
+    }
+
+    /// Add whole units of a named color, like `ColorMixer::add_colors_str`
+    pub fn add_colors_str(&mut self, color_str: &str, shade: Shade, quantity: u32, alpha: u8) -> Result<()> {
+        let (r, g, b) = Color::from_shade(color_str, shade)?.rgb();
+        self.add_weighted(Color::with_alpha(r, g, b, alpha), quantity as f32)
+    }
+
+    /// Sum of all weights in the mixer
+    pub fn total_weight(&self) -> f32 {
+        self.colors.iter().map(|(_, weight)| weight).sum()
+    }
+
+    /// Get each distinct color with its total weight, in order of first insertion
+    pub fn color_weights(&self) -> Vec<(Color, f32)> {
+        self.colors.clone()
+    }
+
+    /// Get the weight-normalized mix of the colors
+    pub fn get_mixed_color(&self) -> Result<Color> {
+        mix_weighted(&self.colors, self.mix_mode)
+    }
+}
+
+/// Total number of units in a list of color counts
+fn count_units(colors: &[(Color, u32)]) -> usize {
+    colors.iter().map(|(_, count)| *count as usize).sum()
+}
+
+/// Mix a list of colors, one unit each, with the given mode
+pub fn mix_colors(colors: &[Color], mode: MixMode) -> Result<Color> {
+    let counts: Vec<(Color, u32)> = colors.iter().map(|color| (color.clone(), 1)).collect();
+    mix_counts(&counts, mode)
+}
+
+/// Mix a list of distinct colors and their unit counts with the given mode
+pub fn mix_counts(colors: &[(Color, u32)], mode: MixMode) -> Result<Color> {
+    mix_counts_rounded(colors, mode, RoundingMode::default())
+}
+
+/// Mix a list of distinct colors and their unit counts with the given mode, rounding the channels as given
+pub fn mix_counts_rounded(colors: &[(Color, u32)], mode: MixMode, rounding: RoundingMode) -> Result<Color> {
+    let weights: Vec<(Color, f64)> = colors
+        .iter()
+        .map(|(color, count)| (color.clone(), *count as f64))
+        .collect();
+    mix_weights(&weights, mode, rounding)
+}
+
+/// Mix a list of colors and their relative weights (e.g. 1.5 parts to 0.5 parts) with the given mode.
+/// Weights must be finite and non-negative.
+pub fn mix_weighted(colors: &[(Color, f32)], mode: MixMode) -> Result<Color> {
+    mix_weighted_rounded(colors, mode, RoundingMode::default())
+}
+
+/// Mix a list of colors and their relative weights with the given mode, rounding the channels as given
+pub fn mix_weighted_rounded(colors: &[(Color, f32)], mode: MixMode, rounding: RoundingMode) -> Result<Color> {
+    if let Some(&(_, weight)) = colors.iter().find(|(_, weight)| !is_valid_weight(*weight)) {
+        return Err(ColorMixerError::InvalidWeight(weight));
+    }
+
+    let weights: Vec<(Color, f64)> = colors
+        .iter()
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|(color, weight)| (color.clone(), *weight as f64))
+        .collect();
+    mix_weights(&weights, mode, rounding)
+}
+
+/// Whether a weight can take part in a mix
+fn is_valid_weight(weight: f32) -> bool {
+    weight.is_finite() && weight >= 0.0
+}
+
+/// Mix colors weighted by units, which may be fractional
+fn mix_weights(colors: &[(Color, f64)], mode: MixMode, rounding: RoundingMode) -> Result<Color> {
+    let units: f64 = colors.iter().map(|(_, units)| units).sum();
+    if units == 0.0 {
+        return Err(ColorMixerError::NoColors);
+    }
+
+    let mut present = colors.iter().filter(|(_, units)| *units > 0.0).map(|(color, _)| color.rgb());
+    let first = present.next();
+    let (r, g, b) = if present.all(|rgb| Some(rgb) == first) {
+        // One color mixes to itself in every mode, at any count and opacity
+        first.expect("some units are present")
+    } else if colors.iter().all(|(color, _)| color.alpha() == 0) {
+        // Fully transparent colors contribute nothing. With nothing else to mix, the
+        // channels are mixed as if opaque, and the mix stays transparent
+        let opaque: Vec<(Color, f64)> = colors
+            .iter()
+            .map(|(color, units)| {
+                let (r, g, b) = color.rgb();
+                (Color::new(r, g, b), *units)
+            })
+            .collect();
+        mix_weights(&opaque, mode, rounding)?.rgb()
+    } else {
+        match mode {
+            MixMode::Additive => additive_mix(colors, rounding)?,
+            MixMode::Subtractive => subtractive_mix(colors, rounding),
+            MixMode::LinearAverage => linear_mix(colors, rounding),
+            MixMode::DirectAverage => direct_mix(colors, rounding),
+        }
+        .rgb()
+    };
+
+    // The mix is as opaque as its ingredients are on average
+    let alpha_sum: f64 = colors.iter().map(|(color, units)| color.alpha() as f64 * units).sum();
+    Ok(Color::with_alpha(r, g, b, rounding.divide(alpha_sum, units)))
+}
+
+/// Mix pigments by multiplying their normalized reflectances.
+///
+/// Each channel is the geometric mean of the channel across all colors, so
+/// equal parts yellow and blue only keep the light both of them reflect
+/// (green), just like mixing paint. Each unit is weighted by its alpha.
+///
+/// The channels are offset by `PIGMENT_OFFSET` first. A zero channel would otherwise make
+/// the mean zero whatever its weight, turning 99 yellow and 1 blue green and red and yellow
+/// red; with the offset they give a yellow and an orange.
+fn subtractive_mix(colors: &[(Color, f64)], rounding: RoundingMode) -> Color {
+    let mut total_weight = 0.0f64;
+    let mut log_sums = [0.0f64; 3];
+
+    for (weight, logs) in per_color(colors, subtractive_term) {
+        for (sum, log) in log_sums.iter_mut().zip(logs) {
+            *sum += log;
+        }
+        total_weight += weight;
+    }
+
+    let [r, g, b] = log_sums.map(|sum| rounding.apply((sum / total_weight).exp() - PIGMENT_OFFSET));
+    Color::new(r, g, b)
+}
+
+/// A color's weight in a subtractive mix and its weighted log reflectances
+fn subtractive_term((color, units): &(Color, f64)) -> (f64, [f64; 3]) {
+    let weight = color.alpha() as f64 * units;
+    let (r, g, b) = color.rgb();
+    (weight, [r, g, b].map(|channel| weight * (channel as f64 + PIGMENT_OFFSET).ln()))
+}
+
+/// Mix lights by averaging the channels in linear light.
+///
+/// sRGB values are gamma encoded, so averaging them directly makes bright mixes too dark.
+/// Each unit is weighted by its alpha.
+fn linear_mix(colors: &[(Color, f64)], rounding: RoundingMode) -> Color {
+    let mut total_weight = 0.0f64;
+    let mut sums = [0.0f64; 3];
+
+    for (weight, linear) in per_color(colors, linear_term) {
+        for (sum, term) in sums.iter_mut().zip(linear) {
+            *sum += term;
+        }
+        total_weight += weight;
+    }
+
+    let [r, g, b] = sums.map(|sum| rounding.apply(linear_to_srgb(sum / total_weight) * 255.0));
+    Color::new(r, g, b)
+}
+
+/// A color's weight in a linear mix and its weighted channels in linear light
+fn linear_term((color, units): &(Color, f64)) -> (f64, [f64; 3]) {
+    let weight = color.alpha() as f64 * units;
+    let (r, g, b) = color.rgb();
+    (weight, [r, g, b].map(|channel| weight * srgb_to_linear(channel)))
+}
+
+/// Work out each color's contribution to a mix, in order. With the `parallel` feature, large
+/// sets are spread over threads; the caller still sums the results in order, so the mix is
+/// bit for bit the same either way.
+fn per_color<T: Send>(colors: &[(Color, f64)], term: impl Fn(&(Color, f64)) -> T + Sync) -> Vec<T> {
+    #[cfg(feature = "parallel")]
+    if colors.len() >= PARALLEL_THRESHOLD {
+        use rayon::prelude::*;
+        return colors.par_iter().map(&term).collect();
+    }
+
+    colors.iter().map(term).collect()
+}
+
+/// Multiply a vector by a 3×3 matrix
+fn transform(matrix: &[[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+/// Decode an sRGB channel into linear light in 0..1
+fn srgb_to_linear(channel: u8) -> f64 {
+    let x = channel as f64 / 255.0;
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The nonlinear compression of a white-relative XYZ coordinate in the CIELAB formulas
+fn lab_f(t: f64) -> f64 {
+    const DELTA: f64 = 6.0 / 29.0;
+    if t > DELTA.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+/// Euclidean distance between two CIELAB colors, the CIE76 Delta-E
+fn lab_distance((l1, a1, b1): (f64, f64, f64), (l2, a2, b2): (f64, f64, f64)) -> f64 {
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+/// Encode linear light in 0..1 back into an sRGB value in 0..1
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Mix lights by averaging the RGB channels of every unit as they are, weighted by alpha.
+///
+/// Unlike `additive_mix`, colors a few units off a shade keep their own channels, so e.g.
+/// #FCEA00 and blue mix to something slightly different from yellow and blue.
+fn direct_mix(colors: &[(Color, f64)], rounding: RoundingMode) -> Color {
+    let mut total_weight = 0.0f64;
+    let mut sums = [0.0f64; 3];
+
+    for (color, units) in colors {
+        let weight = color.alpha() as f64 * units;
+        let (r, g, b) = color.rgb();
+        for (sum, channel) in sums.iter_mut().zip([r, g, b]) {
+            *sum += channel as f64 * weight;
+        }
+        total_weight += weight;
+    }
+
+    let [r, g, b] = sums.map(|sum| rounding.divide(sum, total_weight));
+    Color::new(r, g, b)
+}
+
+/// Mix lights by averaging the shades of each color family, then weighting the family
+/// averages by their share. Colors within `CLASSIFY_TOLERANCE` of a shade count as that
+/// shade, as in `family_counts`; the others are averaged together as one more family.
+fn additive_mix(colors: &[(Color, f64)], rounding: RoundingMode) -> Result<Color> {
+    // Count the units of each color family and sum their shades' channels, weighting
+    // every unit by its alpha. Colors outside the families share one extra bucket
+    // with their own channels, mixed in like another family.
+    let mut counts = [0.0f64; FAMILY_COUNT + 1];
+    let mut sums = [[0.0f64; 3]; FAMILY_COUNT + 1];
+
+    for (color, units) in colors {
+        let (bucket, (r, g, b)) = match color.kind_near(CLASSIFY_TOLERANCE) {
+            Some(kind) => (kind.family as usize, kind.family.color(kind.shade).rgb()),
+            None => (FAMILY_COUNT, color.rgb()),
+        };
+        let weight = color.alpha() as f64 * units;
+        counts[bucket] += weight;
+        for (sum, channel) in sums[bucket].iter_mut().zip([r, g, b]) {
+            *sum += channel as f64 * weight;
+        }
+    }
+
+    let total: f64 = counts.iter().sum();
+    let present: Vec<usize> = (0..counts.len()).filter(|&bucket| counts[bucket] > 0.0).collect();
+
+    // If there's only one bucket, calculate the average of its colors
+    if let [family] = present[..] {
+        let [r, g, b] = sums[family].map(|sum| rounding.divide(sum, counts[family]));
+        return Ok(Color::new(r, g, b));
+    }
+
+    // Mix the families, weighting each family's average by its share of the total
+    let mut mixed = [0.0f64; 3];
+    for family in present {
+        let ratio = counts[family] / total;
+        for (channel, sum) in mixed.iter_mut().zip(sums[family]) {
+            *channel += (sum / counts[family]) * ratio;
+        }
+    }
+
+    let [r, g, b] = mixed.map(|channel| rounding.apply(channel));
+    Ok(Color::new(r, g, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mix the named standard shades in the given mode, `quantity` units each
+    fn mix(mode: MixMode, colors: &[(&str, u32)]) -> Color {
+        let mut mixer = ColorMixer::new();
+        mixer.set_mix_mode(mode);
+        for &(name, quantity) in colors {
+            mixer.add_colors_str(name, Shade::Standard, quantity, OPAQUE).unwrap();
+        }
+        mixer.get_mixed_color().unwrap()
+    }
+
+    #[test]
+    fn subtractive_yellow_and_blue_make_green() {
+        let (r, g, b) = mix(MixMode::Subtractive, &[("yellow", 1), ("blue", 1)]).rgb();
+        assert!(g > r && g > b, "green should dominate, got ({r}, {g}, {b})");
+    }
+
+    #[test]
+    fn subtractive_small_share_only_tints() {
+        let (r, g, b) = mix(MixMode::Subtractive, &[("yellow", 99), ("blue", 1)]).rgb();
+        assert!(r > 200 && g > 200 && b < 40, "should stay yellow, got ({r}, {g}, {b})");
+
+        let (r, g, b) = mix(MixMode::Subtractive, &[("yellow", 99), ("red", 1)]).rgb();
+        assert!(r > 200 && g > 200 && b < 40, "should stay yellow, got ({r}, {g}, {b})");
+    }
+
+    #[test]
+    fn adding_zero_units_changes_nothing() {
+        let mut mixer = ColorMixer::new();
+        assert!(matches!(mixer.add_colors_str("red", Shade::Standard, 0, OPAQUE), Err(ColorMixerError::ZeroQuantity)));
+        assert!(mixer.is_empty());
+        assert!(mixer.color_counts().is_empty());
+        assert!(mixer.history().is_empty());
+        assert!(matches!(mixer.undo(), Err(ColorMixerError::NothingToUndo)));
+        assert_eq!(mixer.clear(), 0);
+        assert!(mixer.history().is_empty());
+    }
+
+    #[test]
+    fn repeated_units_share_one_entry() {
+        let mut mixer = ColorMixer::new();
+        for _ in 0..9 {
+            mixer.add_colors_str("yellow", Shade::Standard, 100, OPAQUE).unwrap();
+        }
+        assert_eq!(mixer.len(), 900);
+        assert_eq!(mixer.color_counts().len(), 1);
+        assert_eq!(mixer.get_mixed_color().unwrap(), Family::Yellow.color(Shade::Standard));
+    }
+
+    #[test]
+    fn undo_reverses_adds_and_clears() {
+        let mut mixer = ColorMixer::new();
+        assert!(matches!(mixer.undo(), Err(ColorMixerError::NothingToUndo)));
+
+        mixer.add_colors_str("yellow", Shade::Standard, 2, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Light, 1, OPAQUE).unwrap();
+        let before = mixer.color_counts();
+        mixer.clear();
+        mixer.undo().unwrap();
+        assert_eq!(mixer.color_counts(), before);
+
+        mixer.undo().unwrap();
+        assert_eq!(mixer.color_counts(), vec![(Family::Yellow.color(Shade::Standard), 2)]);
+        mixer.undo().unwrap();
+        assert!(mixer.is_empty());
+        assert!(matches!(mixer.undo(), Err(ColorMixerError::NothingToUndo)));
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip_every_change() {
+        let families = [Family::Yellow, Family::Blue, Family::Red];
+        let [yellow, blue, red] = families.map(|family| family.color(Shade::Standard));
+        let mut mixer = ColorMixer::new();
+        let mut states = vec![mixer.color_counts()];
+        mixer.add_batch(vec![(yellow.clone(), 2), (blue.clone(), 1), (yellow.clone(), 1), (red.clone(), 4)]).unwrap();
+        states.push(mixer.color_counts());
+        mixer.remove_colors(&blue, 1).unwrap();
+        states.push(mixer.color_counts());
+        mixer.set_quantity(&yellow, 1).unwrap();
+        states.push(mixer.color_counts());
+        mixer.set_quantity(&blue, 2).unwrap();
+        states.push(mixer.color_counts());
+        mixer.clear_family(Family::Red);
+        states.push(mixer.color_counts());
+        mixer.replace_colors(vec![(red.clone(), 1)]).unwrap();
+        states.push(mixer.color_counts());
+        mixer.clear();
+        states.push(mixer.color_counts());
+
+        for state in states.iter().rev().skip(1) {
+            mixer.undo().unwrap();
+            assert_eq!(&mixer.color_counts(), state);
+        }
+        for state in states.iter().skip(1) {
+            mixer.redo().unwrap();
+            assert_eq!(&mixer.color_counts(), state);
+        }
+        assert!(matches!(mixer.redo(), Err(ColorMixerError::NothingToRedo)));
+    }
+
+    #[test]
+    fn undo_history_is_bounded() {
+        let mut mixer = ColorMixer::new();
+        mixer.set_max_undo(3);
+        for _ in 0..5 {
+            mixer.add_colors_str("red", Shade::Standard, 1, OPAQUE).unwrap();
+        }
+        for _ in 0..3 {
+            mixer.undo().unwrap();
+        }
+        assert!(matches!(mixer.undo(), Err(ColorMixerError::NothingToUndo)));
+        assert_eq!(mixer.len(), 2);
+
+        mixer.set_max_undo(1);
+        mixer.redo().unwrap();
+        assert!(matches!(mixer.redo(), Err(ColorMixerError::NothingToRedo)));
+        assert_eq!(mixer.len(), 3);
+    }
+
+    #[test]
+    fn removing_nothing_leaves_nothing_to_undo() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("blue", Shade::Standard, 2, OPAQUE).unwrap();
+        assert_eq!(mixer.remove_colors_str("blue", Shade::Standard, 0).unwrap(), 0);
+        assert!(mixer.remove_colors_str("red", Shade::Standard, 1).is_err());
+        assert_eq!(mixer.history().len(), 1);
+
+        assert_eq!(mixer.remove_colors_str("blue", Shade::Standard, 5).unwrap(), 2);
+        mixer.undo().unwrap();
+        assert_eq!(mixer.len(), 2);
+        mixer.undo().unwrap();
+        assert!(mixer.is_empty());
+        assert!(matches!(mixer.undo(), Err(ColorMixerError::NothingToUndo)));
+    }
+
+    #[test]
+    fn red_and_yellow_make_orange() {
+        for mode in [MixMode::default(), MixMode::Additive] {
+            let mixed = mix(mode, &[("red", 1), ("yellow", 1)]);
+            let (hue, _, _) = mixed.to_hsl();
+            assert!((10.0..45.0).contains(&hue), "{mode:?} should give an orange, got {mixed}");
+        }
+    }
+
+    #[test]
+    fn yellow_and_blue_alone_mix_to_their_average() {
+        // (2 * 255 + 0) / 3, (2 * 237 + 71) / 3 and (2 * 0 + 171) / 3, as with only two families
+        let mixed = mix(MixMode::Additive, &[("yellow", 2), ("blue", 1)]);
+        assert_eq!(mixed.to_hex(), "#AAB639");
+    }
+
+    #[test]
+    fn direct_average_keeps_colors_near_a_shade_as_they_are() {
+        let blue = Family::Blue.color(Shade::Standard);
+        let off_yellow = Color::new(252, 234, 0);
+        let shades = [(Family::Yellow.color(Shade::Standard), 1), (blue.clone(), 1)];
+        let near = [(off_yellow, 1), (blue, 1)];
+
+        // Family-weighted: the off yellow counts as yellow, (255 + 0) / 2, (237 + 71) / 2, 171 / 2
+        assert_eq!(mix_counts(&near, MixMode::Additive).unwrap().to_hex(), "#809A56");
+        assert_eq!(mix_counts(&near, MixMode::Additive).unwrap(), mix_counts(&shades, MixMode::Additive).unwrap());
+        // Direct: (252 + 0) / 2, (234 + 71) / 2, 171 / 2
+        assert_eq!(mix_counts(&near, MixMode::DirectAverage).unwrap().to_hex(), "#7E9956");
+    }
+
+    #[test]
+    fn preview_fails_like_add() {
+        let mut mixer = ColorMixer::new();
+        mixer.set_max_per_request(10);
+        mixer.set_max_per_color(Some(15));
+        mixer.add_colors_str("blue", Shade::Standard, 10, OPAQUE).unwrap();
+        let blue = Family::Blue.color(Shade::Standard);
+
+        for quantity in [0, 5, 6, 11, 500] {
+            let preview = mixer.preview_with(&blue, quantity);
+            let added = mixer.clone().add_colors(blue.clone(), quantity);
+            assert_eq!(preview.is_ok(), added.is_ok(), "quantity {quantity}");
+            if let (Err(preview), Err(added)) = (preview, added) {
+                assert_eq!(preview.to_string(), added.to_string());
+            }
+        }
+        assert_eq!(mixer.len(), 10);
+    }
+
+    #[test]
+    fn reset_loads_one_unit_of_each_default_shade() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("teal", Shade::Standard, 5, OPAQUE).unwrap();
+        mixer.reset_to_default().unwrap();
+        let counts = mixer.color_counts();
+        assert_eq!(counts.len(), 6);
+        let families: Vec<Option<Family>> =
+            counts.iter().map(|(color, _)| color.kind().map(|kind| kind.family)).collect();
+        assert_eq!(families, [[Some(Family::Yellow); 3], [Some(Family::Blue); 3]].concat());
+        assert!(counts.iter().all(|(_, count)| *count == 1));
+        mixer.undo().unwrap();
+        assert_eq!(mixer.color_counts(), [("teal".parse::<Color>().unwrap(), 5)]);
+    }
+
+    #[test]
+    fn one_color_mixes_to_itself_at_any_count() {
+        let modes = [MixMode::Additive, MixMode::Subtractive, MixMode::LinearAverage, MixMode::DirectAverage];
+        let roundings = [RoundingMode::Floor, RoundingMode::Round, RoundingMode::Ceil];
+        let mut colors: Vec<Color> = SHADES.iter().map(|&(_, (r, g, b))| Color::new(r, g, b)).collect();
+        colors.extend([Color::new(254, 238, 1), Color::new(18, 43, 60), Color::with_alpha(0, 71, 171, 128)]);
+
+        for color in &colors {
+            for (mode, rounding) in modes.iter().flat_map(|mode| roundings.map(|rounding| (*mode, rounding))) {
+                for units in [1, 2, 5, 100] {
+                    let mixed = mix_counts_rounded(&[(color.clone(), units)], mode, rounding).unwrap();
+                    assert_eq!(&mixed, color, "{units} x {color} in {mode:?} with {rounding:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn whole_channels_survive_the_linear_round_trip() {
+        let colors = [(Color::new(255, 237, 0), 1), (Color::new(0, 237, 255), 1)];
+        let (_, g, _) = mix_counts_rounded(&colors, MixMode::LinearAverage, RoundingMode::Floor).unwrap().rgb();
+        assert_eq!(g, 237);
+    }
+
+    #[test]
+    fn rounding_modes_differ_on_fractional_channels() {
+        // Red is 253 / 4 = 63.25 and green 255 / 4 = 63.75
+        let colors = [(Color::new(253, 255, 0), 1), (Color::new(0, 0, 0), 3)];
+        let hex = |rounding| mix_counts_rounded(&colors, MixMode::DirectAverage, rounding).unwrap().to_hex();
+        assert_eq!(hex(RoundingMode::Floor), "#3F3F00");
+        assert_eq!(hex(RoundingMode::Round), "#3F4000");
+        assert_eq!(hex(RoundingMode::Ceil), "#404000");
+    }
+
+    #[test]
+    fn opaque_colors_outweigh_transparent_ones() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("yellow", Shade::Standard, 1, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 1, 128).unwrap();
+        let mixed = mixer.get_mixed_color().unwrap();
+        let (yellow, blue) = (Family::Yellow.color(Shade::Standard), Family::Blue.color(Shade::Standard));
+        assert!(mixed.delta_e(&yellow) < mixed.delta_e(&blue), "yellow should dominate, got {mixed}");
+
+        mixer.add_colors_str("red", Shade::Standard, 5, 0).unwrap();
+        assert_eq!(mixer.get_mixed_color().unwrap().rgb(), mixed.rgb());
+    }
+
+    #[test]
+    fn transparent_colors_alone_mix_transparent() {
+        let red = Color::with_alpha(255, 0, 0, 0);
+        for units in [1, 2] {
+            assert_eq!(mix_counts(&[(red.clone(), units)], MixMode::default()).unwrap(), red);
+        }
+
+        let blue = Color::with_alpha(0, 0, 255, 0);
+        let mixed = mix_counts(&[(red, 1), (blue, 1)], MixMode::Additive).unwrap();
+        assert_eq!(mixed.to_rgba_hex(), "#80008000");
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_terms_are_bit_identical_to_sequential() {
+        // Scatter the channels, opacities and counts with a multiplicative hash
+        let colors: Vec<(Color, f64)> = (0..PARALLEL_THRESHOLD as u32 * 2)
+            .map(|i| {
+                let [r, g, b, alpha] = i.wrapping_mul(2_654_435_761).to_le_bytes();
+                (Color::with_alpha(r, g, b, alpha), (i % 9 + 1) as f64)
+            })
+            .collect();
+        let bits = |terms: Vec<(f64, [f64; 3])>| -> Vec<[u64; 4]> {
+            terms.into_iter().map(|(weight, [r, g, b])| [weight, r, g, b].map(f64::to_bits)).collect()
+        };
+
+        // The terms are the only part computed on other threads; the sums over them run in order either way
+        for term in [subtractive_term, linear_term] {
+            assert_eq!(bits(per_color(&colors, term)), bits(colors.iter().map(term).collect()));
+        }
+    }
+
+    #[test]
+    fn redo_keeps_to_a_lowered_per_color_limit() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("red", Shade::Standard, 3, 255).unwrap();
+        mixer.undo().unwrap();
+        mixer.set_max_per_color(Some(2));
+        assert!(matches!(mixer.redo(), Err(ColorMixerError::ColorQuantityExceeded { limit: 2, .. })));
+        assert!(mixer.is_empty());
+
+        mixer.set_max_per_color(Some(3));
+        mixer.redo().unwrap();
+        assert_eq!(mixer.len(), 3);
+    }
+
+    #[test]
+    fn composition_adds_up_to_exactly_100() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("yellow", Shade::Standard, 2, 255).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 1, 255).unwrap();
+        let composition = mixer.composition();
+        assert_eq!((composition.yellow_pct, composition.blue_pct), (66.7, 33.3));
+
+        mixer.add_colors_str("red", Shade::Standard, 1, 255).unwrap();
+        mixer.remove_colors_str("yellow", Shade::Standard, 1).unwrap();
+        let Composition { yellow_pct, blue_pct, red_pct, green_pct, other_pct } = mixer.composition();
+        assert_eq!((yellow_pct, blue_pct, red_pct), (33.4, 33.3, 33.3));
+        let tenths: f64 = [yellow_pct, blue_pct, red_pct, green_pct, other_pct].iter().map(|pct| pct * 10.0).sum();
+        assert_eq!(tenths.round(), 1000.0);
+    }
+
+    #[test]
+    fn family_counts_hold_more_than_u32_units() {
+        let mut mixer = ColorMixer::with_max_colors(usize::MAX);
+        mixer.set_max_per_request(u32::MAX);
+        mixer.add_colors_str("blue", Shade::Light, u32::MAX, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Dark, u32::MAX, OPAQUE).unwrap();
+        mixer.add_colors_str("teal", Shade::Standard, 2, OPAQUE).unwrap();
+        assert_eq!(mixer.family_counts().blue, 2 * u64::from(u32::MAX));
+
+        let composition = mixer.composition();
+        assert_eq!((composition.blue_pct, composition.other_pct), (100.0, 0.0));
+    }
+
+    #[test]
+    fn aliases_name_custom_shades_before_css_colors() {
+        for (alias, shade) in SHADE_ALIASES {
+            assert_eq!(alias.parse::<Color>().unwrap(), shade.parse::<Color>().unwrap(), "{alias}");
+        }
+        assert_eq!("navy".parse::<Color>().unwrap().rgb(), (0, 32, 91));
+        assert_eq!("Gold".parse::<Color>().unwrap().rgb(), (204, 187, 0));
+    }
+
+    #[test]
+    fn hex_parses_in_any_case() {
+        assert_eq!("#1E90FF".parse::<Color>().unwrap().rgb(), (30, 144, 255));
+        assert_eq!("#1e90ff".parse::<Color>().unwrap().to_hex(), "#1E90FF");
+        for invalid in ["#12345", "#GGGGGG", "#", "#1234567"] {
+            assert!(matches!(invalid.parse::<Color>(), Err(ColorMixerError::InvalidHex(_))), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn shorthand_hex_doubles_each_digit() {
+        assert_eq!("#FF0".parse::<Color>().unwrap().to_hex(), "#FFFF00");
+        assert_eq!("#abc".parse::<Color>().unwrap().to_hex(), "#AABBCC");
+    }
+
+    #[test]
+    fn hsl_and_hsv_convert_both_ways() {
+        let coral = "coral".parse::<Color>().unwrap();
+        let (h, s, l) = coral.to_hsl();
+        assert_eq!(Color::from_hsl(h, s, l), coral);
+        assert_eq!(Color::from_hsl(120.0, 100.0, 50.0).rgb(), (0, 255, 0));
+        assert_eq!(Color::new(255, 0, 0).to_hsv(), (0.0, 100.0, 100.0));
+        assert_eq!(Color::new(128, 128, 128).to_hsv().1, 0.0);
+    }
+
+    #[test]
+    fn removes_match_any_opacity_newest_first() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("red", Shade::Standard, 2, OPAQUE).unwrap();
+        mixer.add_colors_str("red", Shade::Standard, 2, 128).unwrap();
+        assert_eq!(mixer.remove_colors_str("red", Shade::Standard, 3).unwrap(), 3);
+        assert_eq!(mixer.color_counts(), [(Color::new(255, 0, 0), 1)]);
+
+        assert_eq!(mixer.remove_colors_str("red", Shade::Standard, 10).unwrap(), 1);
+        assert!(mixer.is_empty());
+        let missing = mixer.remove_colors_str("red", Shade::Standard, 1);
+        assert!(matches!(missing, Err(ColorMixerError::ColorNotFound(_))));
+    }
+
+    #[test]
+    fn large_quantities_do_not_overflow() {
+        let mut mixer = ColorMixer::with_max_colors(usize::MAX);
+        mixer.set_max_per_request(u32::MAX);
+        mixer.add_colors_str("yellow", Shade::Standard, u32::MAX, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, u32::MAX, OPAQUE).unwrap();
+        assert_eq!(mixer.len(), 2 * u32::MAX as usize);
+        assert_eq!(mixer.get_mixed_color().unwrap(), mix(MixMode::default(), &[("yellow", 1), ("blue", 1)]));
+    }
+
+    #[test]
+    fn max_colors_caps_the_units() {
+        let mut mixer = ColorMixer::with_max_colors(5);
+        mixer.add_colors_str("red", Shade::Standard, 3, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 2, OPAQUE).unwrap();
+        let full = mixer.add_colors_str("green", Shade::Standard, 1, OPAQUE);
+        assert!(matches!(full, Err(ColorMixerError::MaxColorsReached)));
+        assert_eq!(mixer.len(), 5);
+        assert!(matches!(mixer.set_max_colors(4), Err(ColorMixerError::MaxColorsBelowCount { max: 4, current: 5 })));
+        mixer.set_max_colors(6).unwrap();
+        mixer.add_colors_str("green", Shade::Standard, 1, OPAQUE).unwrap();
+    }
+
+    #[test]
+    fn css_names_resolve_after_the_shades() {
+        assert_eq!("rebeccapurple".parse::<Color>().unwrap().to_hex(), "#663399");
+        assert_eq!("Teal".parse::<Color>().unwrap().to_hex(), "#008080");
+        // The custom shades win over the CSS colors of the same name
+        assert_eq!("yellow".parse::<Color>().unwrap().rgb(), (255, 237, 0));
+        assert!(matches!("mauve".parse::<Color>(), Err(ColorMixerError::UnsupportedColor(_))));
+    }
+
+    #[test]
+    fn names_are_found_exactly_or_nearest() {
+        assert_eq!(Color::new(255, 237, 0).name(), Some("yellow"));
+        assert_eq!(Color::new(0, 128, 128).name(), Some("teal"));
+        assert_eq!(Color::new(1, 128, 128).name(), None);
+        assert_eq!(Color::new(1, 128, 128).nearest_name(), "teal");
+    }
+
+    #[test]
+    fn family_counts_cover_every_family() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_family(Family::Red, Shade::Light, 2).unwrap();
+        mixer.add_family(Family::Green, Shade::Standard, 1).unwrap();
+        mixer.add_colors_str("teal", Shade::Standard, 1, OPAQUE).unwrap();
+        assert_eq!(mixer.family_counts(), FamilyCounts { yellow: 0, blue: 0, red: 2, green: 1 });
+    }
+
+    #[test]
+    fn arbitrary_colors_snap_to_the_nearest_shade() {
+        assert_eq!(Color::new(0, 0, 0).distance(&Color::new(3, 4, 0)), 5.0);
+        assert_eq!(Color::new(254, 238, 1).snap_to_palette(), Family::Yellow.color(Shade::Standard));
+        assert_eq!(Color::with_alpha(5, 30, 90, 7).snap_to_palette(), Color::with_alpha(0, 32, 91, 7));
+    }
+
+    #[test]
+    fn mix_functions_agree_with_the_mixer() {
+        let (yellow, blue) = (Family::Yellow.color(Shade::Standard), Family::Blue.color(Shade::Standard));
+        let expected = mix(MixMode::Additive, &[("yellow", 2), ("blue", 1)]);
+        assert_eq!(mix_colors(&[yellow.clone(), yellow.clone(), blue.clone()], MixMode::Additive).unwrap(), expected);
+        assert_eq!(mix_counts(&[(yellow, 2), (blue, 1)], MixMode::Additive).unwrap(), expected);
+        assert!(matches!(mix_colors(&[], MixMode::Additive), Err(ColorMixerError::NoColors)));
+    }
+
+    #[test]
+    fn linear_mix_is_brighter_than_the_srgb_average() {
+        let srgb = mix(MixMode::DirectAverage, &[("red", 1), ("green", 1)]);
+        let linear = mix(MixMode::LinearAverage, &[("red", 1), ("green", 1)]);
+        assert_eq!(srgb.rgb(), (128, 128, 0));
+        assert_eq!(linear.rgb(), (188, 188, 0));
+    }
+
+    #[test]
+    fn cmyk_converts_both_ways() {
+        assert_eq!(Color::new(0, 0, 0).to_cmyk(), (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(Color::new(255, 0, 0).to_cmyk(), (0.0, 1.0, 1.0, 0.0));
+        let coral = "coral".parse::<Color>().unwrap();
+        let (c, m, y, k) = coral.to_cmyk();
+        assert_eq!(Color::from_cmyk(c, m, y, k), coral);
+        assert_eq!(Color::from_cmyk(2.0, -1.0, 0.0, 0.0).rgb(), (0, 255, 255));
+    }
+
+    #[test]
+    fn luminance_classifies_light_and_dark() {
+        let (black, white) = (Color::new(0, 0, 0), Color::new(255, 255, 255));
+        assert_eq!((black.relative_luminance(), white.relative_luminance()), (0.0, 1.0));
+        assert!(white.is_light() && !black.is_light());
+        assert!("yellow".parse::<Color>().unwrap().is_light());
+        assert!(!"blue".parse::<Color>().unwrap().is_light());
+    }
+
+    #[test]
+    fn contrast_runs_from_1_to_21() {
+        let (black, white) = (Color::new(0, 0, 0), Color::new(255, 255, 255));
+        assert_eq!(black.contrast_ratio(&white), 21.0);
+        assert_eq!(white.contrast_ratio(&black), 21.0);
+        assert_eq!(white.contrast_ratio(&white), 1.0);
+    }
+
+    #[test]
+    fn harmonies_rotate_the_hue() {
+        let red = Color::new(255, 0, 0);
+        assert_eq!(red.complement().rgb(), (0, 255, 255));
+        assert_eq!(red.triadic().map(|color| color.rgb()), [(255, 0, 0), (0, 255, 0), (0, 0, 255)]);
+        assert_eq!(red.analogous()[1], red);
+        assert_eq!(red.harmony(HarmonyScheme::Complement), [red.clone(), red.complement()]);
+    }
+
+    #[test]
+    fn gradients_include_both_ends() {
+        let (black, white) = (Color::new(0, 0, 0), Color::new(255, 255, 255));
+        let gradient = black.gradient(&white, 3).unwrap();
+        assert_eq!(gradient, [black.clone(), Color::new(128, 128, 128), white.clone()]);
+        assert!(matches!(black.gradient(&white, 1), Err(ColorMixerError::InvalidSteps { .. })));
+    }
+
+    #[test]
+    fn blend_modes_match_their_formulas() {
+        let (gray, red) = (Color::new(128, 128, 128), Color::new(255, 0, 0));
+        assert_eq!(gray.blend(&red, BlendMode::Multiply).rgb(), (128, 0, 0));
+        assert_eq!(gray.blend(&red, BlendMode::Screen).rgb(), (255, 128, 128));
+        assert_eq!(Color::new(0, 255, 64).blend(&gray, BlendMode::Overlay).rgb(), (0, 255, 64));
+    }
+
+    #[test]
+    #[cfg(feature = "persistence")]
+    fn state_survives_a_save_and_load() {
+        let path = std::env::temp_dir().join(format!("color_mixer_lib_{}.json", std::process::id()));
+        let mut mixer = ColorMixer::with_max_colors(50);
+        mixer.add_colors_str("red", Shade::Standard, 2, 128).unwrap();
+        mixer.set_mix_mode(MixMode::Additive);
+        mixer.snapshot("reds");
+        mixer.save_to_path(&path).unwrap();
+
+        let loaded = ColorMixer::load_from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.color_counts(), mixer.color_counts());
+        assert_eq!((loaded.max_colors(), loaded.mix_mode()), (50, MixMode::Additive));
+        assert_eq!(loaded.list_snapshots(), ["reds"]);
+        assert!(matches!(ColorMixer::load_from_path(&path), Err(ColorMixerError::Persistence(_))));
+    }
+
+    #[test]
+    fn set_colors_replaces_everything_or_nothing() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("teal", Shade::Standard, 1, OPAQUE).unwrap();
+        let named = vec![("red".to_string(), Shade::Dark, 1), ("mauve".to_string(), Shade::Standard, 1)];
+        assert!(matches!(mixer.set_colors(named), Err(ColorMixerError::InvalidEntry { index: 1, .. })));
+        assert_eq!(mixer.len(), 1);
+
+        mixer.set_colors(vec![("red".to_string(), Shade::Dark, 2)]).unwrap();
+        assert_eq!(mixer.color_counts(), [(Color::new(204, 0, 0), 2)]);
+        mixer.undo().unwrap();
+        assert_eq!(mixer.color_counts(), [("teal".parse::<Color>().unwrap(), 1)]);
+    }
+
+    #[test]
+    fn color_blindness_keeps_grays() {
+        let gray = Color::new(128, 128, 128);
+        for kind in [CvdKind::Protanopia, CvdKind::Deuteranopia, CvdKind::Tritanopia] {
+            assert!(gray.simulate_cvd(kind).is_near(&gray, 1), "{kind:?}");
+        }
+        let (r, g, _) = Color::new(255, 0, 0).simulate_cvd(CvdKind::Protanopia).rgb();
+        assert!(r < 200 && g > 0, "red should lose most of its redness, got {r} and {g}");
+    }
+
+    #[test]
+    fn stats_weigh_colors_by_count() {
+        let mut mixer = ColorMixer::new();
+        assert_eq!(mixer.stats(), ColorStats::default());
+        mixer.add_rgb(0, 0, 0, 1).unwrap();
+        mixer.add_rgb(200, 100, 0, 3).unwrap();
+        let stats = mixer.stats();
+        assert_eq!(stats.mean, (150.0, 75.0, 0.0));
+        assert_eq!(stats.distinct, 2);
+        assert!((stats.std_dev.0 - 86.6).abs() < 0.1);
+    }
+
+    #[test]
+    fn shades_exist_only_for_the_families() {
+        assert_eq!(Color::from_shade("red", Shade::Dark).unwrap().rgb(), (204, 0, 0));
+        assert_eq!(Family::Blue.color(Shade::Light).rgb(), (102, 153, 255));
+        assert!(matches!(Color::from_shade("teal", Shade::Light), Err(ColorMixerError::UnsupportedShade(_))));
+        assert_eq!(Color::from_shade("teal", Shade::Standard).unwrap().to_hex(), "#008080");
+    }
+
+    #[test]
+    fn kind_names_the_family_and_shade() {
+        assert_eq!(Color::new(204, 0, 0).kind(), Some(ColorKind { family: Family::Red, shade: Shade::Dark }));
+        assert_eq!(Color::new(255, 237, 0).kind(), Some(ColorKind { family: Family::Yellow, shade: Shade::Standard }));
+        assert_eq!(Color::new(0, 128, 128).kind(), None);
+    }
+
+    #[test]
+    fn weighted_mixes_match_whole_unit_mixes() {
+        let (yellow, blue) = (Family::Yellow.color(Shade::Standard), Family::Blue.color(Shade::Standard));
+        let weighted = mix_weighted(&[(yellow.clone(), 1.5), (blue.clone(), 0.5)], MixMode::default()).unwrap();
+        assert_eq!(weighted, mix(MixMode::default(), &[("yellow", 3), ("blue", 1)]));
+        let negative = mix_weighted(&[(yellow.clone(), -1.0)], MixMode::default());
+        assert!(matches!(negative, Err(ColorMixerError::InvalidWeight(_))));
+        assert!(matches!(mix_weighted(&[(yellow.clone(), 0.0)], MixMode::default()), Err(ColorMixerError::NoColors)));
+
+        let mut mixer = WeightedMixer::new();
+        mixer.add_weighted(yellow, 0.75).unwrap();
+        mixer.add_weighted(blue, 0.25).unwrap();
+        assert_eq!(mixer.total_weight(), 1.0);
+        assert_eq!(mixer.get_mixed_color().unwrap(), weighted);
+        assert!(mixer.add_weighted(Color::new(0, 0, 0), f32::NAN).is_err());
+    }
+
+    #[test]
+    fn filters_keep_alpha() {
+        let color = Color::with_alpha(200, 100, 50, 64);
+        assert_eq!(color.invert(), Color::with_alpha(55, 155, 205, 64));
+        let gray = color.grayscale();
+        let (r, g, b) = gray.rgb();
+        assert!(r == g && g == b && gray.alpha() == 64);
+        assert_eq!(color.sepia().alpha(), 64);
+        assert_eq!(Color::new(255, 255, 255).sepia().rgb(), (255, 255, 239));
+    }
+
+    #[test]
+    fn adjustments_clamp_and_keep_alpha() {
+        let color = Color::with_alpha(200, 100, 50, 64);
+        assert_eq!(color.lighten(100.0).rgb(), (255, 255, 255));
+        assert_eq!(color.darken(100.0).rgb(), (0, 0, 0));
+        assert_eq!(color.lighten(10.0).alpha(), 64);
+        let (_, saturation, _) = color.desaturate(100.0).to_hsl();
+        assert_eq!(saturation, 0.0);
+        assert_eq!(color.adjust(Adjustment::Saturate, 0.0), color);
+    }
+
+    #[test]
+    fn hue_rotation_wraps_and_leaves_grays() {
+        let red = Color::new(255, 0, 0);
+        assert_eq!(red.rotate_hue(120.0).rgb(), (0, 255, 0));
+        assert_eq!(red.rotate_hue(-240.0), red.rotate_hue(120.0));
+        assert_eq!(red.rotate_hue(720.0), red);
+        assert_eq!(Color::new(90, 90, 90).rotate_hue(45.0), Color::new(90, 90, 90));
+    }
+
+    #[test]
+    fn per_add_limit_rejects_large_adds() {
+        let mut mixer = ColorMixer::new();
+        mixer.set_max_per_request(4);
+        let too_many = mixer.add_colors_str("red", Shade::Standard, 5, OPAQUE);
+        assert!(matches!(too_many, Err(ColorMixerError::QuantityTooLarge(4))));
+        assert!(mixer.is_empty());
+        mixer.add_colors_str("red", Shade::Standard, 4, OPAQUE).unwrap();
+    }
+
+    #[test]
+    fn batches_are_all_or_nothing() {
+        let mut mixer = ColorMixer::new();
+        let red = Color::new(255, 0, 0);
+        let batch = vec![(red.clone(), 2), (Color::new(0, 0, 255), 0)];
+        assert!(matches!(mixer.add_batch(batch), Err(ColorMixerError::InvalidEntry { index: 1, .. })));
+        assert!(mixer.is_empty());
+
+        mixer.add_batch(vec![(red.clone(), 2), (red.clone(), 1)]).unwrap();
+        assert_eq!(mixer.color_counts(), [(red, 3)]);
+        mixer.undo().unwrap();
+        assert!(mixer.is_empty());
+    }
+
+    #[test]
+    fn snapshots_restore_and_undo() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("red", Shade::Standard, 2, OPAQUE).unwrap();
+        mixer.snapshot("reds");
+        mixer.clear();
+        mixer.restore("reds").unwrap();
+        assert_eq!(mixer.len(), 2);
+        mixer.undo().unwrap();
+        assert!(mixer.is_empty());
+        assert_eq!(mixer.list_snapshots(), ["reds"]);
+        assert!(matches!(mixer.restore("blues"), Err(ColorMixerError::SnapshotNotFound(_))));
+    }
+
+    #[test]
+    fn history_lists_changes_in_order() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("red", Shade::Standard, 2, OPAQUE).unwrap();
+        mixer.clear();
+        mixer.undo().unwrap();
+        let ops: Vec<HistoryOp> = mixer.history().iter().map(|entry| entry.op).collect();
+        assert_eq!(ops, [HistoryOp::Add, HistoryOp::Clear, HistoryOp::Undo]);
+        assert_eq!(mixer.history()[0].quantity, Some(2));
+    }
+
+    #[test]
+    fn quantities_are_set_exactly() {
+        let mut mixer = ColorMixer::new();
+        let red = Color::new(255, 0, 0);
+        mixer.set_quantity(&red, 4).unwrap();
+        mixer.set_quantity(&red, 1).unwrap();
+        assert_eq!(mixer.color_counts(), [(red.clone(), 1)]);
+        mixer.undo().unwrap();
+        assert_eq!(mixer.len(), 4);
+        mixer.set_quantity(&red, 0).unwrap();
+        assert!(mixer.is_empty());
+    }
+
+    #[test]
+    fn display_and_order_follow_the_channels() {
+        assert_eq!(Color::new(0, 71, 171).to_string(), "#0047AB");
+        let mut colors = vec![Color::new(2, 0, 0), Color::with_alpha(1, 0, 0, 255), Color::with_alpha(1, 0, 0, 0)];
+        colors.sort();
+        assert_eq!(colors, [Color::with_alpha(1, 0, 0, 0), Color::with_alpha(1, 0, 0, 255), Color::new(2, 0, 0)]);
+        let unique: std::collections::HashSet<Color> = [Color::new(1, 2, 3), Color::new(1, 2, 3)].into();
+        assert_eq!(unique.len(), 1);
+    }
+
+    #[test]
+    fn kinds_allow_a_small_tolerance() {
+        let near_yellow = Color::new(252, 234, 3);
+        assert_eq!(near_yellow.kind(), None);
+        assert_eq!(near_yellow.kind_near(3), Some(ColorKind { family: Family::Yellow, shade: Shade::Standard }));
+        assert_eq!(near_yellow.kind_near(2), None);
+        assert!(Color::new(255, 237, 0).is_yellow() && !near_yellow.is_yellow());
+    }
+
+    #[test]
+    fn iterating_yields_every_unit_in_insertion_order() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("red", Shade::Standard, 2, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 1, OPAQUE).unwrap();
+        let units: Vec<String> = mixer.iter().map(|color| color.to_hex()).collect();
+        assert_eq!(units, ["#FF0000", "#FF0000", "#0047AB"]);
+        assert_eq!((&mixer).into_iter().count(), 3);
+    }
+
+    #[test]
+    fn collecting_counts_one_unit_per_color() {
+        let red = Color::new(255, 0, 0);
+        let mixer: ColorMixer = [red.clone(), Color::new(0, 0, 255), red.clone()].into_iter().collect();
+        assert_eq!(mixer.color_counts(), [(red.clone(), 2), (Color::new(0, 0, 255), 1)]);
+        assert!(mixer.history().is_empty());
+
+        let capped: ColorMixer = std::iter::repeat_n(red, 2000).collect();
+        assert_eq!(capped.len(), capped.max_colors());
+        assert!(ColorMixer::with_capacity(8).is_empty());
+    }
+
+    #[test]
+    fn default_mixer_is_empty_with_the_default_limits() {
+        let mixer = ColorMixer::default();
+        assert!(mixer.is_empty());
+        assert_eq!((mixer.max_colors(), mixer.max_per_request()), (MAX_COLORS, MAX_PER_REQUEST));
+        assert_eq!(mixer.mix_mode(), MixMode::default());
+    }
+
+    #[test]
+    fn from_hex_takes_no_names() {
+        assert_eq!(Color::from_hex("abc").unwrap().to_hex(), "#AABBCC");
+        assert_eq!(Color::from_hex("#00FF00").unwrap().rgb(), (0, 255, 0));
+        assert!(matches!(Color::from_hex("red"), Err(ColorMixerError::InvalidHex(_))));
+    }
+
+    #[test]
+    fn colors_serialize_as_hex() {
+        assert_eq!(serde_json::to_string(&Color::new(255, 0, 0)).unwrap(), "\"#FF0000\"");
+        assert_eq!(serde_json::to_string(&Color::with_alpha(255, 0, 0, 128)).unwrap(), "\"#FF000080\"");
+        let parsed: Color = serde_json::from_str("\"#FF000080\"").unwrap();
+        assert_eq!(parsed, Color::with_alpha(255, 0, 0, 128));
+        // State files written before colors were hex strings still load
+        let legacy: Color = serde_json::from_str(r#"{ "rgb": { "r": 1, "g": 2, "b": 3 }, "alpha": 4 }"#).unwrap();
+        assert_eq!(legacy, Color::with_alpha(1, 2, 3, 4));
+    }
+
+    #[test]
+    fn cached_mix_is_dropped_on_every_change() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("yellow", Shade::Standard, 1, OPAQUE).unwrap();
+        assert_eq!(mixer.get_mixed_color().unwrap(), Family::Yellow.color(Shade::Standard));
+        mixer.add_colors_str("blue", Shade::Standard, 1, OPAQUE).unwrap();
+        let mixed = mixer.get_mixed_color().unwrap();
+        assert_eq!(mixed, mix(MixMode::default(), &[("yellow", 1), ("blue", 1)]));
+        mixer.set_mix_mode(MixMode::Additive);
+        assert_ne!(mixer.get_mixed_color().unwrap(), mixed);
+        mixer.undo().unwrap();
+        assert_eq!(mixer.get_mixed_color().unwrap(), Family::Yellow.color(Shade::Standard));
+    }
+
+    #[test]
+    fn delta_e_measures_perceived_difference() {
+        let white = Color::new(255, 255, 255);
+        let (l, a, b) = white.to_lab();
+        assert!((l - 100.0).abs() < 0.01 && a.abs() < 0.01 && b.abs() < 0.01);
+        assert_eq!(white.delta_e(&white), 0.0);
+        assert!((white.delta_e(&Color::new(0, 0, 0)) - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn kelvin_runs_from_warm_to_cool() {
+        let (r, _, b) = Color::from_kelvin(2000.0).rgb();
+        assert!(r == 255 && b < 50, "2000 K should be orange, got {r} and {b}");
+        let (r, g, b) = Color::from_kelvin(6500.0).rgb();
+        assert!(r.min(g).min(b) > 240, "6500 K should be near white, got ({r}, {g}, {b})");
+        let (r, _, b) = Color::from_kelvin(10000.0).rgb();
+        assert!(b == 255 && r < 255, "10000 K should be blue, got {r} and {b}");
+        assert_eq!(Color::from_kelvin(1.0), Color::from_kelvin(1000.0));
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn sampling_picks_colors_by_their_counts() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let mut mixer = ColorMixer::new();
+        assert!(matches!(mixer.sample(&mut StdRng::seed_from_u64(1)), Err(ColorMixerError::NoColors)));
+        mixer.add_colors_str("red", Shade::Standard, 3, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 1, OPAQUE).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let reds = (0..4000).filter(|_| mixer.sample(&mut rng).unwrap().is_red()).count();
+        assert!((2800..3200).contains(&reds), "red should be picked about 3 in 4 times, got {reds} of 4000");
+    }
+
+    #[test]
+    fn clear_family_keeps_the_other_families() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_family(Family::Yellow, Shade::Light, 1).unwrap();
+        mixer.add_family(Family::Yellow, Shade::Dark, 2).unwrap();
+        mixer.add_family(Family::Blue, Shade::Standard, 1).unwrap();
+        assert_eq!(mixer.clear_family(Family::Yellow), 3);
+        assert_eq!(mixer.clear_family(Family::Yellow), 0);
+        assert_eq!(mixer.color_counts(), [(Family::Blue.color(Shade::Standard), 1)]);
+        mixer.undo().unwrap();
+        assert_eq!(mixer.len(), 4);
+    }
+
+    #[test]
+    fn ramps_end_in_white_or_black() {
+        let (red, white, black) = (Color::new(255, 0, 0), Color::new(255, 255, 255), Color::new(0, 0, 0));
+        let tints = red.tints(4).unwrap();
+        assert_eq!((tints.len(), tints.first(), tints.last()), (5, Some(&red), Some(&white)));
+        assert_eq!(red.shades(2).unwrap().last(), Some(&black));
+        assert_eq!(Color::with_alpha(255, 0, 0, 9).shades(1).unwrap()[1], Color::with_alpha(0, 0, 0, 9));
+        assert!(matches!(red.tints(0), Err(ColorMixerError::InvalidSteps { .. })));
+    }
+
+    #[test]
+    fn temperature_follows_the_hue() {
+        assert_eq!(Color::new(255, 0, 0).temperature(), Temperature::Warm);
+        assert_eq!(Color::new(255, 237, 0).temperature(), Temperature::Warm);
+        assert_eq!(Color::new(0, 71, 171).temperature(), Temperature::Cool);
+        assert_eq!(Color::new(128, 128, 128).temperature(), Temperature::Neutral);
+    }
+
+    #[test]
+    fn per_color_limit_counts_each_color() {
+        let mut mixer = ColorMixer::new();
+        mixer.set_max_per_color(Some(3));
+        mixer.add_colors_str("red", Shade::Standard, 2, OPAQUE).unwrap();
+        let over = mixer.add_colors_str("red", Shade::Standard, 2, OPAQUE);
+        assert!(matches!(over, Err(ColorMixerError::ColorQuantityExceeded { limit: 3, .. })));
+        mixer.add_colors_str("blue", Shade::Standard, 3, OPAQUE).unwrap();
+        // Another opacity is another color
+        mixer.add_colors_str("red", Shade::Standard, 3, 128).unwrap();
+    }
+
+    #[test]
+    fn hex_without_hash_suggests_the_closest_shade() {
+        let Err(ColorMixerError::UnsupportedColor(message)) = "feee01".parse::<Color>() else {
+            panic!("bare hex digits should be rejected");
+        };
+        assert_eq!(message, "Unsupported color: #FEEE01 (hex values need a leading #), did you mean 'yellow'?");
+    }
+
+    #[test]
+    fn names_ignore_case_and_separators() {
+        let light_blue = Family::Blue.color(Shade::Light);
+        for name in ["light-blue", "light blue", "light_blue", "Light-Blue", "  LIGHT   blue "] {
+            assert_eq!(name.parse::<Color>().unwrap(), light_blue, "{name:?}");
+        }
+        for name in ["dark-yellow", "dark yellow", "dark_yellow", "DARK_Yellow"] {
+            assert_eq!(name.parse::<Color>().unwrap(), Family::Yellow.color(Shade::Dark), "{name:?}");
+        }
+    }
 
-        Ok(Color::new(r, g, b))
+    #[test]
+    fn distinct_colors_are_sorted_by_channel() {
+        let mut mixer = ColorMixer::new();
+        mixer.add_colors_str("yellow", Shade::Standard, 3, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 1, OPAQUE).unwrap();
+        mixer.add_colors_str("blue", Shade::Standard, 1, 128).unwrap();
+        let distinct = [Color::with_alpha(0, 71, 171, 128), Color::new(0, 71, 171), Color::new(255, 237, 0)];
+        assert_eq!(mixer.distinct_colors(), distinct);
     }
 }
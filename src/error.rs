@@ -1,16 +1,251 @@
+#[cfg(feature = "server")]
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection, StringRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+#[cfg(feature = "server")]
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ColorMixerError {
-    #[error("Unsupported color: {0}")]
+    #[error("{0}")]
     UnsupportedColor(String),
-    
+
+    #[error("{0}")]
+    InvalidHex(String),
+
+    #[error("{0}")]
+    UnsupportedShade(String),
+
+    #[error("Color cannot be empty")]
+    EmptyColor,
+
+    #[error("Quantity must be greater than zero")]
+    ZeroQuantity,
+
+    #[error("Weight must be a finite, non-negative number, got {0}")]
+    InvalidWeight(f32),
+
+    #[error("CMYK components must be between 0 and 1, got {0}")]
+    InvalidCmyk(f32),
+
+    #[error("HSL hue must be at least 0 and below 360, saturation and lightness between 0 and 100, got {0:?}")]
+    InvalidHsl([f32; 3]),
+
     #[error("Maximum number of colors reached")]
     MaxColorsReached,
-    
+
+    #[error("Quantity must be at most {0} per request")]
+    QuantityTooLarge(u32),
+
+    #[error("Color {color} is limited to {limit} units")]
+    ColorQuantityExceeded { color: String, limit: u32 },
+
+    #[error("Cannot lower the maximum to {max} colors, the mixer already holds {current}")]
+    MaxColorsBelowCount { max: usize, current: usize },
+
+    #[error("Steps must be between {min} and {max}")]
+    InvalidSteps { min: usize, max: usize },
+
+    #[error("{0}")]
+    ColorNotFound(String),
+
+    #[error("Session {0} not found")]
+    SessionNotFound(String),
+
+    #[error("Snapshot {0} not found")]
+    SnapshotNotFound(String),
+
+    #[error("No endpoint at {0}")]
+    RouteNotFound(String),
+
     #[error("No colors to mix")]
     NoColors,
+
+    #[error("Nothing to undo")]
+    NothingToUndo,
+
+    #[error("Nothing to redo")]
+    NothingToRedo,
+
+    #[error("Missing or invalid bearer token")]
+    Unauthorized,
+
+    #[error("Too many requests, retry in {0} s")]
+    RateLimited(u64),
+
+    #[error("{0}")]
+    Persistence(String),
+
+    #[error("Invalid request body: {0}")]
+    InvalidBody(String),
+
+    #[error("Request body too large: {0}")]
+    BodyTooLarge(String),
+
+    #[error("Invalid query string: {0}")]
+    InvalidQuery(String),
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    #[error("Line {line}: {message}")]
+    InvalidCsv { line: usize, message: String },
+
+    #[error("Entry {index}: {source}")]
+    InvalidEntry {
+        index: usize,
+        source: Box<ColorMixerError>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, ColorMixerError>;
 
+/// JSON body returned for every error response
+#[cfg(feature = "server")]
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+    /// Stable machine-readable error code
+    code: String,
+    /// Human readable description of the error
+    message: String,
+    /// `"not found"` for paths that match no endpoint, in the shape of a plain 404
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    /// The path that matched no endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+impl ColorMixerError {
+    /// HTTP status code the error maps to
+    #[cfg(feature = "server")]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ColorMixerError::UnsupportedColor(_)
+            | ColorMixerError::InvalidHex(_)
+            | ColorMixerError::UnsupportedShade(_)
+            | ColorMixerError::EmptyColor
+            | ColorMixerError::ZeroQuantity
+            | ColorMixerError::InvalidWeight(_)
+            | ColorMixerError::InvalidCmyk(_)
+            | ColorMixerError::InvalidHsl(_)
+            | ColorMixerError::MaxColorsReached
+            | ColorMixerError::QuantityTooLarge(_)
+            | ColorMixerError::ColorQuantityExceeded { .. }
+            | ColorMixerError::MaxColorsBelowCount { .. }
+            | ColorMixerError::InvalidSteps { .. }
+            | ColorMixerError::NoColors
+            | ColorMixerError::NothingToUndo
+            | ColorMixerError::NothingToRedo
+            | ColorMixerError::InvalidBody(_)
+            | ColorMixerError::InvalidQuery(_)
+            | ColorMixerError::InvalidPath(_)
+            | ColorMixerError::InvalidCsv { .. } => StatusCode::BAD_REQUEST,
+            ColorMixerError::ColorNotFound(_)
+            | ColorMixerError::SessionNotFound(_)
+            | ColorMixerError::SnapshotNotFound(_)
+            | ColorMixerError::RouteNotFound(_) => StatusCode::NOT_FOUND,
+            ColorMixerError::BodyTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ColorMixerError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ColorMixerError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ColorMixerError::Persistence(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ColorMixerError::InvalidEntry { source, .. } => source.status_code(),
+        }
+    }
+
+    /// Stable machine-readable code identifying the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ColorMixerError::UnsupportedColor(_) => "UNSUPPORTED_COLOR",
+            ColorMixerError::InvalidHex(_) => "INVALID_HEX",
+            ColorMixerError::UnsupportedShade(_) => "UNSUPPORTED_SHADE",
+            ColorMixerError::EmptyColor => "EMPTY_COLOR",
+            ColorMixerError::ZeroQuantity => "ZERO_QUANTITY",
+            ColorMixerError::InvalidWeight(_) => "INVALID_WEIGHT",
+            ColorMixerError::InvalidCmyk(_) => "INVALID_CMYK",
+            ColorMixerError::InvalidHsl(_) => "INVALID_HSL",
+            ColorMixerError::MaxColorsReached => "MAX_COLORS_REACHED",
+            ColorMixerError::QuantityTooLarge(_) => "QUANTITY_TOO_LARGE",
+            ColorMixerError::ColorQuantityExceeded { .. } => "COLOR_QUANTITY_EXCEEDED",
+            ColorMixerError::MaxColorsBelowCount { .. } => "MAX_COLORS_BELOW_COUNT",
+            ColorMixerError::InvalidSteps { .. } => "INVALID_STEPS",
+            ColorMixerError::ColorNotFound(_) => "COLOR_NOT_FOUND",
+            ColorMixerError::SessionNotFound(_) => "SESSION_NOT_FOUND",
+            ColorMixerError::SnapshotNotFound(_) => "SNAPSHOT_NOT_FOUND",
+            ColorMixerError::RouteNotFound(_) => "ROUTE_NOT_FOUND",
+            ColorMixerError::NoColors => "NO_COLORS",
+            ColorMixerError::NothingToUndo => "NOTHING_TO_UNDO",
+            ColorMixerError::NothingToRedo => "NOTHING_TO_REDO",
+            ColorMixerError::Unauthorized => "UNAUTHORIZED",
+            ColorMixerError::RateLimited(_) => "RATE_LIMITED",
+            ColorMixerError::Persistence(_) => "PERSISTENCE_FAILED",
+            ColorMixerError::InvalidBody(_) => "INVALID_BODY",
+            ColorMixerError::BodyTooLarge(_) => "BODY_TOO_LARGE",
+            ColorMixerError::InvalidQuery(_) => "INVALID_QUERY",
+            ColorMixerError::InvalidPath(_) => "INVALID_PATH",
+            ColorMixerError::InvalidCsv { .. } => "INVALID_CSV",
+            // Report why the entry failed, the message says which one
+            ColorMixerError::InvalidEntry { source, .. } => source.code(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<JsonRejection> for ColorMixerError {
+    fn from(rejection: JsonRejection) -> Self {
+        // Bodies over the size limit are rejected while reading, before any JSON is parsed
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return ColorMixerError::BodyTooLarge(rejection.body_text());
+        }
+        ColorMixerError::InvalidBody(rejection.body_text())
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<StringRejection> for ColorMixerError {
+    fn from(rejection: StringRejection) -> Self {
+        // As with JSON, bodies over the size limit are rejected while reading
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return ColorMixerError::BodyTooLarge(rejection.body_text());
+        }
+        ColorMixerError::InvalidBody(rejection.body_text())
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<QueryRejection> for ColorMixerError {
+    fn from(rejection: QueryRejection) -> Self {
+        ColorMixerError::InvalidQuery(rejection.body_text())
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<PathRejection> for ColorMixerError {
+    fn from(rejection: PathRejection) -> Self {
+        ColorMixerError::InvalidPath(rejection.body_text())
+    }
+}
+
+#[cfg(feature = "server")]
+impl IntoResponse for ColorMixerError {
+    fn into_response(self) -> Response {
+        metrics::counter!("color_mixer_errors_total", "kind" => self.code()).increment(1);
+
+        let path = match &self {
+            ColorMixerError::RouteNotFound(path) => Some(path.clone()),
+            _ => None,
+        };
+        let body = ErrorBody {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            error: path.as_ref().map(|_| "not found"),
+            path,
+        };
+        (self.status_code(), Json(body)).into_response()
+    }
+}
@@ -0,0 +1,23 @@
+//! Color mixing logic behind the Color Mixer web server.
+//!
+//! ```
+//! use color_mixer::{ColorMixer, MixMode, Shade};
+//!
+//! let mut mixer = ColorMixer::new();
+//! mixer.set_mix_mode(MixMode::Additive);
+//! mixer.add_colors_str("yellow", Shade::Standard, 2, 255).unwrap();
+//! mixer.add_colors_str("blue", Shade::Light, 1, 255).unwrap();
+//!
+//! let mixed = mixer.get_mixed_color().unwrap();
+//! assert_eq!(mixed.to_hex(), "#CCD155");
+//! ```
+
+pub mod color_mixer;
+mod css_colors;
+pub mod error;
+
+pub use crate::color_mixer::{
+    mix_colors, mix_counts, mix_counts_rounded, mix_weighted, mix_weighted_rounded, Color, ColorMixer, Family, MixMode,
+    RoundingMode, Shade, WeightedMixer,
+};
+pub use crate::error::{ColorMixerError, Result};
@@ -1,99 +1,2813 @@
-mod color_mixer;
-mod error;
-
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{
+        rejection::StringRejection,
+        ws::{Message, Utf8Bytes, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, FromRequest, FromRequestParts, OptionalFromRequestParts, OriginalUri,
+        Path as UrlPath, Query, Request, State,
+    },
+    http::{
+        header::{ACCEPT, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER, VARY},
+        request::Parts,
+        HeaderMap, HeaderValue, Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
+use futures_util::{future, stream, Stream, StreamExt};
+use governor::clock::{Clock, DefaultClock};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use image::{ImageFormat, Rgba, RgbaImage};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, watch, Notify, RwLock};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::services::ServeDir;
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{Level, Span};
+use tracing_subscriber::EnvFilter;
 use serde::Serialize;
 
-use crate::color_mixer::{AddColorRequest, ColorMixer};
-use crate::error::ColorMixerError;
+use color_mixer::color_mixer::{
+    AddColorQuery, AddColorRequest, AdjustRequest, BlendRequest, ColorStats, Composition, ContrastRequest, CvdQuery,
+    DeltaERequest, ExportFormat, ExportQuery, FamilyCounts, FilterQuery, GradientRequest, HistoryEntry, ImportQuery,
+    MixQuery, MixRequest, PaletteQuery, PngQuery, RampQuery, RandomQuery, RotateRequest, SampleQuery, SvgQuery,
+    Temperature,
+};
+use color_mixer::{mix_weighted_rounded, Color, ColorMixer, ColorMixerError, Family, MixMode};
+#[cfg(feature = "docs")]
+use color_mixer::color_mixer::{CvdKind, Filter, HarmonyScheme, RampDirection, RoundingMode};
+#[cfg(feature = "docs")]
+use color_mixer::error::ErrorBody;
+#[cfg(feature = "docs")]
+use utoipa::OpenApi;
+#[cfg(feature = "docs")]
+use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
 
 #[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
 struct ColorResponse {
     color: String,
     rgb: (u8, u8, u8),
+    rgba: String,
+    hsl: (f32, f32, f32),
+    hsv: (f32, f32, f32),
+    cmyk: (f32, f32, f32, f32),
+    is_light: bool,
+    temperature: Temperature,
+    name: Option<&'static str>,
+    nearest_name: &'static str,
+}
+
+impl From<&Color> for ColorResponse {
+    fn from(color: &Color) -> Self {
+        Self {
+            color: color.to_hex(),
+            rgb: color.rgb(),
+            rgba: color.to_rgba_hex(),
+            hsl: color.to_hsl(),
+            hsv: color.to_hsv(),
+            cmyk: color.to_cmyk(),
+            is_light: color.is_light(),
+            temperature: color.temperature(),
+            name: color.name(),
+            nearest_name: color.nearest_name(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct RemoveResponse {
+    removed: usize,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct ColorCountResponse {
+    hex: String,
+    rgb: (u8, u8, u8),
+    count: u32,
+}
+
+/// JSON extractor that reports malformed bodies as a `ColorMixerError`
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ColorMixerError))]
+struct ApiJson<T>(T);
+
+/// Query string extractor that reports invalid parameters as a `ColorMixerError`
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(ColorMixerError))]
+struct ApiQuery<T>(T);
+
+/// Session used by the routes without a session segment
+const DEFAULT_SESSION: &str = "default";
+
+/// First path segments of the unscoped API routes. A session with one of these names would be
+/// shadowed by the unscoped routes, e.g. `POST /api/restore/color` restores the snapshot `color`.
+const RESERVED_SESSIONS: [&str; 32] = [
+    "blend", "clear", "color", "color.png", "color.svg", "colors", "contrast", "count", "deltae", "events", "export",
+    "export.csv", "gradient", "health", "history", "import.csv", "mix", "openapi.json", "palette", "preview", "ramp",
+    "random", "ready", "redo", "reset", "restore", "sample", "snapshot", "snapshots", "stats", "undo", "ws",
+];
+
+/// Session named in the `/api/{session}/...` path, or the default session on the unscoped routes.
+/// The names in `RESERVED_SESSIONS` are rejected.
+struct SessionId(String);
+
+impl<S: Send + Sync> FromRequestParts<S> for SessionId {
+    type Rejection = ColorMixerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Read the parameters by name, as some routes have more than the session
+        let params = <UrlPath<HashMap<String, String>> as OptionalFromRequestParts<S>>::from_request_parts(parts, state)
+            .await?;
+        let session = params.and_then(|UrlPath(mut params)| params.remove("session"));
+        if let Some(reserved) = session.as_deref().filter(|id| RESERVED_SESSIONS.contains(id)) {
+            return Err(ColorMixerError::InvalidPath(format!("session ID '{reserved}' is reserved for a route")));
+        }
+        Ok(SessionId(session.unwrap_or_else(|| DEFAULT_SESSION.to_string())))
+    }
+}
+
+/// Snapshot named in the `.../snapshot/{name}` and `.../restore/{name}` paths
+struct SnapshotName(String);
+
+impl<S: Send + Sync> FromRequestParts<S> for SnapshotName {
+    type Rejection = ColorMixerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let UrlPath(mut params) =
+            <UrlPath<HashMap<String, String>> as FromRequestParts<S>>::from_request_parts(parts, state).await?;
+        let name = params.remove("name").ok_or_else(|| ColorMixerError::InvalidPath("missing snapshot name".into()))?;
+        Ok(SnapshotName(name))
+    }
+}
+
+/// Family named in the `.../colors/family/{family}` path
+struct FamilyName(Family);
+
+impl<S: Send + Sync> FromRequestParts<S> for FamilyName {
+    type Rejection = ColorMixerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let UrlPath(mut params) =
+            <UrlPath<HashMap<String, String>> as FromRequestParts<S>>::from_request_parts(parts, state).await?;
+        let family = params.remove("family").ok_or_else(|| ColorMixerError::InvalidPath("missing family".into()))?;
+        Ok(FamilyName(family.parse()?))
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct SnapshotsResponse {
+    snapshots: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct CountResponse {
+    total: usize,
+    #[serde(flatten)]
+    families: FamilyCounts,
+}
+
+/// Minimum WCAG contrast ratio for normal text at level AA
+const WCAG_AA_RATIO: f64 = 4.5;
+
+/// Minimum WCAG contrast ratio for normal text at level AAA
+const WCAG_AAA_RATIO: f64 = 7.0;
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct StatusResponse {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct ContrastResponse {
+    ratio: f64,
+    passes_aa: bool,
+    passes_aaa: bool,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "docs", derive(utoipa::ToSchema))]
+struct DeltaEResponse {
+    delta_e: f64,
+    reference: (f64, f64, f64),
+    sample: (f64, f64, f64),
+}
+
+type Sessions = HashMap<String, ColorMixer>;
+
+/// Number of updates a slow subscriber can fall behind before it skips ahead
+const UPDATE_CHANNEL_CAPACITY: usize = 16;
+
+/// Response bodies up to this many bytes are sent uncompressed, gzip or brotli would barely shrink them
+const COMPRESSION_MIN_BYTES: u16 = 1024;
+
+/// Largest request body accepted unless `MAX_BODY_BYTES` says otherwise
+const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Colors a client may add per second unless `RATE_LIMIT` says otherwise
+const DEFAULT_RATE_LIMIT: NonZeroU32 = NonZeroU32::new(20).unwrap();
+
+/// How often clients that stopped adding colors are dropped from the rate limiter
+const RATE_LIMIT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Token bucket per client address for adding colors
+type AddLimiter = Arc<DefaultKeyedRateLimiter<IpAddr>>;
+
+/// The add limiter and how it tells clients apart
+#[derive(Clone)]
+struct AddLimit {
+    limiter: AddLimiter,
+    /// Key clients by the address the proxy in front appended to `X-Forwarded-For`, as `TRUST_PROXY` allows;
+    /// otherwise anyone could dodge the limit by sending a new address in the header each time
+    trust_proxy: bool,
+}
+
+/// How long the sessions are left to settle after a change before they are saved, so a burst of
+/// changes is written once
+const SAVE_DELAY: Duration = Duration::from_millis(200);
+
+/// Address the server listens on unless `HOST` says otherwise
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// Port the server listens on unless `PORT` says otherwise
+const DEFAULT_PORT: u16 = 8080;
+
+/// The mixed color of a session after a change, `None` once it is empty
+#[derive(Clone)]
+struct Update {
+    session: String,
+    color: Option<Color>,
+}
+
+/// Color carried by each Server-Sent Event
+#[derive(Serialize)]
+struct ColorEvent {
+    hex: String,
+    rgb: (u8, u8, u8),
+}
+
+impl From<&Color> for ColorEvent {
+    fn from(color: &Color) -> Self {
+        Self {
+            hex: color.to_hex(),
+            rgb: color.rgb(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    /// Every session's mixer, by session ID
+    sessions: Arc<RwLock<Sessions>>,
+    /// Limit given to the mixers of sessions created on demand
+    max_colors: usize,
+    /// Per-add limit given to the mixers of sessions created on demand
+    max_per_request: u32,
+    /// Per-color limit given to the mixers of sessions created on demand
+    max_per_color: Option<u32>,
+    /// Number of changes the mixers of sessions created on demand can undo
+    max_undo: usize,
+    /// Where the sessions are saved after every change, if persistence is enabled
+    state_file: Option<Arc<Path>>,
+    /// Wakes the task saving the sessions to `state_file` after a change
+    save: Arc<Notify>,
+    /// Every change to a session's colors, for the WebSocket and event stream subscribers
+    updates: broadcast::Sender<Update>,
+    /// Set once the state is loaded and the server is listening
+    ready: Arc<AtomicBool>,
+    /// Renders the recorded metrics for scraping
+    metrics: PrometheusHandle,
+    /// Becomes `true` when the server starts shutting down, which ends the open streams
+    shutdown: watch::Receiver<bool>,
+}
+
+impl AppState {
+    /// State serving `sessions` with the default mixer limits and no state file; override
+    /// the rest with struct update syntax
+    fn new(sessions: Sessions, metrics: PrometheusHandle, shutdown: watch::Receiver<bool>) -> Self {
+        let defaults = ColorMixer::default();
+        AppState {
+            sessions: Arc::new(RwLock::new(sessions)),
+            max_colors: defaults.max_colors(),
+            max_per_request: defaults.max_per_request(),
+            max_per_color: defaults.max_per_color(),
+            max_undo: defaults.max_undo(),
+            state_file: None,
+            save: Arc::default(),
+            updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
+            ready: Arc::default(),
+            metrics,
+            shutdown,
+        }
+    }
+
+    /// Have the sessions saved after session `id` changed and push its new color to the subscribers.
+    /// `save_changes` does the saving once the caller has let go of the lock.
+    fn changed(&self, sessions: &Sessions, id: &str) {
+        if self.state_file.is_some() {
+            self.save.notify_one();
+        }
+
+        let units: usize = sessions.values().map(ColorMixer::len).sum();
+        metrics::gauge!("color_mixer_colors").set(units as f64);
+
+        // Sending only fails when nobody is subscribed
+        let _ = self.updates.send(Update {
+            session: id.to_string(),
+            color: mixed_color(sessions, id),
+        });
+    }
+
+    /// Empty mixer with the configured limits
+    fn new_mixer(&self) -> ColorMixer {
+        let mut mixer = ColorMixer::with_max_colors(self.max_colors);
+        mixer.set_max_per_request(self.max_per_request);
+        mixer.set_max_per_color(self.max_per_color);
+        mixer.set_max_undo(self.max_undo);
+        mixer
+    }
+
+    /// Apply `change` to session `id`, starting the session if needed. A session started here is
+    /// dropped again when the change fails, so a rejected first request leaves no empty session.
+    fn start_or_change<T>(
+        &self,
+        sessions: &mut Sessions,
+        id: &str,
+        change: impl FnOnce(&mut ColorMixer) -> Result<T, ColorMixerError>,
+    ) -> Result<T, ColorMixerError> {
+        let started = !sessions.contains_key(id);
+        let mixer = sessions.entry(id.to_string()).or_insert_with(|| self.new_mixer());
+        let changed = change(mixer);
+        if started && changed.is_err() {
+            sessions.remove(id);
+        }
+        changed
+    }
+
+    /// Write the sessions to `path`, serializing them under the read lock and writing the file off
+    /// the async workers. Failures are logged, since the changes being saved already went through.
+    async fn save_to(&self, path: &Arc<Path>) -> bool {
+        let json = serde_json::to_string_pretty(&*self.sessions.read().await);
+        let file = path.clone();
+        let written = match json {
+            Ok(json) => tokio::task::spawn_blocking(move || write_state(&file, &json))
+                .await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err))),
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = &written {
+            tracing::error!("Failed to write {}: {}", path.display(), err);
+        }
+        written.is_ok()
+    }
+
+    /// Current mixed color of session `id`, read when a subscriber connects or falls behind
+    async fn current_color(&self, id: &str) -> Option<Color> {
+        mixed_color(&*self.sessions.read().await, id)
+    }
+}
+
+/// Mixed color of a session, `None` when it is missing or empty
+fn mixed_color(sessions: &Sessions, id: &str) -> Option<Color> {
+    sessions.get(id).and_then(|mixer| mixer.get_mixed_color().ok())
+}
+
+/// Wait for the next change to session `id`, or `None` once the server shuts down
+async fn next_update(updates: &mut broadcast::Receiver<Update>, state: &AppState, id: &str) -> Option<Option<Color>> {
+    let mut shutdown = state.shutdown.clone();
+    loop {
+        let update = tokio::select! {
+            update = updates.recv() => update,
+            _ = shutdown.wait_for(|&stopping| stopping) => return None,
+        };
+        match update {
+            Ok(update) if update.session == id => return Some(update.color),
+            Ok(_) => {}
+            // Too far behind to replay the missed changes, so catch up with the latest color
+            Err(broadcast::error::RecvError::Lagged(_)) => return Some(state.current_color(id).await),
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// WebSocket message with a mixed color in the `GET /api/color` shape, `null` when there is none
+fn color_message(color: Option<&Color>) -> Utf8Bytes {
+    serde_json::to_string(&color.map(ColorResponse::from))
+        .expect("color responses always serialize")
+        .into()
+}
+
+/// Server-Sent Event with the hex and RGB of a mixed color, `null` when there is none
+fn color_event(color: Option<&Color>) -> Result<Event, axum::Error> {
+    Event::default().event("color").json_data(color.map(ColorEvent::from))
+}
+
+/// Response to a change that went through: the new mixed color in `mode`, or the mixer's own
+/// mode, and `204 No Content` when the change left nothing to mix
+fn mixed_response(mixer: &ColorMixer, mode: Option<MixMode>) -> Response {
+    match mixer.get_mixed_color_with(mode.unwrap_or(mixer.mix_mode())) {
+        Ok(color) => Json(ColorResponse::from(&color)).into_response(),
+        Err(_) => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Look up an existing session
+fn session<'a>(sessions: &'a Sessions, id: &str) -> Result<&'a ColorMixer, ColorMixerError> {
+    sessions
+        .get(id)
+        .ok_or_else(|| ColorMixerError::SessionNotFound(id.to_string()))
 }
 
-type AppState = Arc<RwLock<ColorMixer>>;
+/// Look up an existing session for modification
+fn session_mut<'a>(sessions: &'a mut Sessions, id: &str) -> Result<&'a mut ColorMixer, ColorMixerError> {
+    sessions
+        .get_mut(id)
+        .ok_or_else(|| ColorMixerError::SessionNotFound(id.to_string()))
+}
+
+/// Reject requests that can never add or remove anything
+fn validate_request(payload: &AddColorRequest) -> Result<(), ColorMixerError> {
+    if !payload.names_color() {
+        return Err(ColorMixerError::EmptyColor);
+    }
+
+    if payload.quantity == 0 {
+        return Err(ColorMixerError::ZeroQuantity);
+    }
+
+    Ok(())
+}
 
+#[tracing::instrument(skip_all, fields(session = %id, color = tracing::field::Empty, quantity = payload.quantity))]
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/color",
+    tag = "mixer",
+    params(AddColorQuery),
+    request_body = AddColorRequest,
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
 async fn add_color(
     State(state): State<AppState>,
-    Json(payload): Json<AddColorRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    if payload.color.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Color cannot be empty".to_string()));
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<AddColorQuery>,
+    ApiJson(payload): ApiJson<AddColorRequest>,
+) -> Result<Response, ColorMixerError> {
+    validate_request(&payload)?;
+
+    let mut color = payload.to_color()?;
+    if query.snap {
+        color = color.snap_to_palette();
     }
+    Span::current().record("color", tracing::field::display(&color));
 
-    if payload.quantity == 0 {
-        return Err((StatusCode::BAD_REQUEST, "Quantity must be greater than zero".to_string()));
+    // Adding or setting the colors is the only way to start a session
+    let mut sessions = state.sessions.write().await;
+    state.start_or_change(&mut sessions, &id, |mixer| mixer.add_colors(color, payload.quantity))?;
+    state.changed(&sessions, &id);
+    metrics::counter!("color_mixer_adds_total").increment(1);
+    tracing::debug!("Added the colors");
+
+    Ok(mixed_response(&sessions[&id], query.mode))
+}
+
+/// Check every entry of a color list, reporting the first invalid one by its index
+fn parse_entries(entries: &[AddColorRequest]) -> Result<Vec<(Color, u32)>, ColorMixerError> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            validate_request(entry)
+                .and_then(|_| entry.to_color())
+                .map(|color| (color, entry.quantity))
+                .map_err(|source| ColorMixerError::InvalidEntry {
+                    index,
+                    source: Box::new(source),
+                })
+        })
+        .collect()
+}
+
+/// Add the listed colors to the session, all or nothing
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/colors/batch",
+    tag = "mixer",
+    request_body = Vec<AddColorRequest>,
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn add_batch(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<Vec<AddColorRequest>>,
+) -> Result<Response, ColorMixerError> {
+    let colors = parse_entries(&payload)?;
+    let added = colors.len() as u64;
+
+    // Like adding one color, a batch starts the session if needed
+    let mut sessions = state.sessions.write().await;
+    state.start_or_change(&mut sessions, &id, |mixer| mixer.add_batch(colors))?;
+    state.changed(&sessions, &id);
+    metrics::counter!("color_mixer_adds_total").increment(added);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+/// Generator for random colors, reproducible when seeded
+fn color_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    }
+}
+
+/// An opaque color with every channel drawn uniformly
+fn random_color(rng: &mut impl Rng) -> Color {
+    Color::new(rng.random(), rng.random(), rng.random())
+}
+
+/// A random color, without adding it to the mixer
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/random",
+    tag = "colors",
+    params(RandomQuery),
+    responses(
+        (status = 200, description = "A random color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn random(ApiQuery(query): ApiQuery<RandomQuery>) -> Json<ColorResponse> {
+    Json(ColorResponse::from(&random_color(&mut color_rng(query.seed))))
+}
+
+/// Add `count` random colors to the session, one unit each
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/random",
+    tag = "mixer",
+    params(RandomQuery),
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn add_random(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<RandomQuery>,
+) -> Result<Response, ColorMixerError> {
+    let count = query.count.unwrap_or(1);
+    if count == 0 {
+        return Err(ColorMixerError::ZeroQuantity);
     }
 
-    let mut mixer = state.write().await;
-    mixer.add_colors_str(&payload.color, &payload.shade, payload.quantity).map_err(|e| match e {
-        ColorMixerError::UnsupportedColor(msg) => (StatusCode::BAD_REQUEST, msg),
-        ColorMixerError::MaxColorsReached => {
-            (StatusCode::BAD_REQUEST, "Maximum number of colors reached".to_string())
+    // Like adding one color, this starts the session if needed
+    let mut sessions = state.sessions.write().await;
+    state.start_or_change(&mut sessions, &id, |mixer| {
+        if count > mixer.max_per_request() {
+            return Err(ColorMixerError::QuantityTooLarge(mixer.max_per_request()));
         }
-        _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
+        let mut rng = color_rng(query.seed);
+        mixer.add_batch((0..count).map(|_| (random_color(&mut rng), 1)).collect())
     })?;
+    state.changed(&sessions, &id);
+    metrics::counter!("color_mixer_adds_total").increment(count.into());
 
-    let color = mixer.get_mixed_color().map_err(|e| match e {
-        ColorMixerError::NoColors => (StatusCode::BAD_REQUEST, "No colors to mix".to_string()),
-        _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
-    })?;
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+/// One of the session's colors, picked at random weighted by its units
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/sample",
+    tag = "mixer",
+    params(SampleQuery),
+    responses(
+        (status = 200, description = "The picked color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn sample(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<SampleQuery>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.sample(&mut color_rng(query.seed))?;
+    Ok(Json(ColorResponse::from(&color)))
+}
+
+/// Replace the session's colors with the listed ones, all or nothing
+#[cfg_attr(feature = "docs", utoipa::path(
+    put,
+    path = "/api/color",
+    tag = "mixer",
+    request_body = Vec<AddColorRequest>,
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn set_colors(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<Vec<AddColorRequest>>,
+) -> Result<Response, ColorMixerError> {
+    let colors = parse_entries(&payload)?;
+
+    // Like adding, setting the colors starts the session if needed
+    let mut sessions = state.sessions.write().await;
+    state.start_or_change(&mut sessions, &id, |mixer| mixer.replace_colors(colors))?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+/// Mix a list of colors in a throwaway mixer, leaving the shared one untouched
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/mix",
+    tag = "colors",
+    request_body = MixRequest,
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn mix_colors(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<MixRequest>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let mut mixer = state.new_mixer();
+
+    for (index, entry) in payload.colors.iter().enumerate() {
+        validate_request(entry)
+            .and_then(|_| entry.to_color())
+            .and_then(|color| mixer.add_colors(color, entry.quantity))
+            .map_err(|source| ColorMixerError::InvalidEntry {
+                index,
+                source: Box::new(source),
+            })?;
+    }
+
+    let color = mixer.get_mixed_color()?;
+    Ok(Json(ColorResponse::from(&color)))
+}
+
+/// Mix colors given as relative parts by name, e.g. `{ "yellow": 70, "blue": 30 }`
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/mix/ratio",
+    tag = "colors",
+    params(MixQuery),
+    request_body = BTreeMap<String, f32>,
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn mix_ratio(
+    ApiQuery(query): ApiQuery<MixQuery>,
+    ApiJson(payload): ApiJson<BTreeMap<String, f32>>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let weights = payload
+        .iter()
+        .map(|(name, weight)| Ok((name.parse()?, *weight)))
+        .collect::<Result<Vec<(Color, f32)>, ColorMixerError>>()?;
+
+    let color = mix_weighted_rounded(&weights, query.mode.unwrap_or_default(), query.rounding.unwrap_or_default())?;
+    Ok(Json(ColorResponse::from(&color)))
+}
+
+/// Show what the mixer would become if the color were added, without adding it
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/preview",
+    tag = "mixer",
+    request_body = AddColorRequest,
+    responses(
+        (status = 200, description = "The mixed color the addition would give", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn preview_color(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<AddColorRequest>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    validate_request(&payload)?;
+
+    let color = payload.to_color()?;
+    let preview = session(&*state.sessions.read().await, &id)?.preview_with(&color, payload.quantity)?;
+
+    Ok(Json(ColorResponse::from(&preview)))
+}
 
-    Ok(Json(ColorResponse {
-        color: color.to_hex(),
-        rgb: color.rgb(),
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/contrast",
+    tag = "colors",
+    request_body = ContrastRequest,
+    responses(
+        (status = 200, description = "Contrast ratio and WCAG levels", body = ContrastResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn contrast(ApiJson(payload): ApiJson<ContrastRequest>) -> Result<Json<ContrastResponse>, ColorMixerError> {
+    let foreground: Color = payload.foreground.parse()?;
+    let background: Color = payload.background.parse()?;
+    let ratio = foreground.contrast_ratio(&background);
+
+    Ok(Json(ContrastResponse {
+        ratio,
+        passes_aa: ratio >= WCAG_AA_RATIO,
+        passes_aaa: ratio >= WCAG_AAA_RATIO,
     }))
 }
 
-async fn get_current_color(
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/deltae",
+    tag = "colors",
+    request_body = DeltaERequest,
+    responses(
+        (status = 200, description = "CIE76 color difference and both colors in CIELAB", body = DeltaEResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn delta_e(ApiJson(payload): ApiJson<DeltaERequest>) -> Result<Json<DeltaEResponse>, ColorMixerError> {
+    let reference: Color = payload.reference.parse()?;
+    let sample: Color = payload.sample.parse()?;
+
+    Ok(Json(DeltaEResponse {
+        delta_e: reference.delta_e(&sample),
+        reference: reference.to_lab(),
+        sample: sample.to_lab(),
+    }))
+}
+
+/// Show the current mixed color as seen with a color vision deficiency
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/color/cvd",
+    tag = "mixer",
+    params(CvdQuery),
+    responses(
+        (status = 200, description = "The mixed color as seen with the deficiency", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn simulate_cvd(
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let mixer = state.read().await;
-    let color = mixer.get_mixed_color().map_err(|e| match e {
-        ColorMixerError::NoColors => (StatusCode::BAD_REQUEST, "No colors to mix".to_string()),
-        _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
-    })?;
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<CvdQuery>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    Ok(Json(ColorResponse::from(&color.simulate_cvd(query.kind))))
+}
+
+/// Apply an image-style filter to the current mixed color
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/color/filter",
+    tag = "mixer",
+    params(FilterQuery),
+    responses(
+        (status = 200, description = "The filtered mixed color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn filter_color(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<FilterQuery>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    Ok(Json(ColorResponse::from(&color.filter(query.name))))
+}
+
+/// Lighten, darken, saturate or desaturate the current mixed color
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/color/adjust",
+    tag = "mixer",
+    request_body = AdjustRequest,
+    responses(
+        (status = 200, description = "The adjusted mixed color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn adjust_color(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<AdjustRequest>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    Ok(Json(ColorResponse::from(&color.adjust(payload.op, payload.amount))))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/color/rotate",
+    tag = "mixer",
+    request_body = RotateRequest,
+    responses(
+        (status = 200, description = "The mixed color with its hue rotated", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn rotate_color(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<RotateRequest>,
+) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    Ok(Json(ColorResponse::from(&color.rotate_hue(payload.degrees))))
+}
+
+/// Build a harmony palette from the current mixed color
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/palette",
+    tag = "mixer",
+    params(PaletteQuery),
+    responses(
+        (status = 200, description = "Palette built from the mixed color", body = Vec<ColorResponse>),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn palette(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<PaletteQuery>,
+) -> Result<Json<Vec<ColorResponse>>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    let palette = color.harmony(query.scheme).iter().map(ColorResponse::from).collect();
+
+    Ok(Json(palette))
+}
+
+/// Build a tint or shade ramp from the current mixed color
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/ramp",
+    tag = "mixer",
+    params(RampQuery),
+    responses(
+        (status = 200, description = "The mixed color followed by its tints or shades", body = Vec<ColorResponse>),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn ramp(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<RampQuery>,
+) -> Result<Json<Vec<ColorResponse>>, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    let ramp = color.ramp_towards(query.direction, query.steps)?.iter().map(ColorResponse::from).collect();
+
+    Ok(Json(ramp))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/gradient",
+    tag = "colors",
+    request_body = GradientRequest,
+    responses(
+        (status = 200, description = "Colors from the first to the last", body = Vec<ColorResponse>),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn gradient(ApiJson(payload): ApiJson<GradientRequest>) -> Result<Json<Vec<ColorResponse>>, ColorMixerError> {
+    let from: Color = payload.from.parse()?;
+    let to: Color = payload.to.parse()?;
+    let gradient = from.gradient(&to, payload.steps)?.iter().map(ColorResponse::from).collect();
+
+    Ok(Json(gradient))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/blend",
+    tag = "colors",
+    request_body = BlendRequest,
+    responses(
+        (status = 200, description = "The blended color", body = ColorResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn blend(ApiJson(payload): ApiJson<BlendRequest>) -> Result<Json<ColorResponse>, ColorMixerError> {
+    let base: Color = payload.base.parse()?;
+    let top: Color = payload.top.parse()?;
+
+    Ok(Json(ColorResponse::from(&base.blend(&top, payload.mode))))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    delete,
+    path = "/api/color",
+    tag = "mixer",
+    request_body = AddColorRequest,
+    responses(
+        (status = 200, description = "Units removed", body = RemoveResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn remove_color(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<AddColorRequest>,
+) -> Result<Json<RemoveResponse>, ColorMixerError> {
+    validate_request(&payload)?;
+
+    let mut sessions = state.sessions.write().await;
+    let mixer = session_mut(&mut sessions, &id)?;
+    let removed = if payload.color.is_empty() {
+        mixer.remove_colors(&payload.to_color()?, payload.quantity)?
+    } else {
+        mixer.remove_colors_str(&payload.color, payload.shade, payload.quantity)?
+    };
+    if removed > 0 {
+        state.changed(&sessions, &id);
+    }
 
-    Ok(Json(ColorResponse {
-        color: color.to_hex(),
-        rgb: color.rgb(),
+    Ok(Json(RemoveResponse {
+        removed: removed as usize,
     }))
 }
 
-async fn clear_colors(State(state): State<AppState>) -> impl IntoResponse {
-    state.write().await.clear();
-    StatusCode::OK
+/// Set the count of one color directly; zero removes it
+#[cfg_attr(feature = "docs", utoipa::path(
+    patch,
+    path = "/api/color",
+    tag = "mixer",
+    request_body = AddColorRequest,
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn set_quantity(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiJson(payload): ApiJson<AddColorRequest>,
+) -> Result<Response, ColorMixerError> {
+    // A quantity of zero is allowed here, so only the color is checked
+    if !payload.names_color() {
+        return Err(ColorMixerError::EmptyColor);
+    }
+    let color = payload.to_color()?;
+
+    let mut sessions = state.sessions.write().await;
+    session_mut(&mut sessions, &id)?.set_quantity(&color, payload.quantity)?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
 }
 
-#[tokio::main]
-async fn main() {
-    // Initialize logger
-    env_logger::init();
+/// Default width and height of the color swatches
+const SWATCH_SIZE: u32 = 64;
 
-    // Create shared state
-    let state = Arc::new(RwLock::new(ColorMixer::new()));
+/// Largest width or height a swatch can be requested at
+const MAX_SWATCH_SIZE: u32 = 1024;
 
-    // Build our application with routes
-    let app = Router::new()
-        .route("/api/color", post(add_color))
-        .route("/api/color", get(get_current_color))
-        .route("/api/clear", post(clear_colors))
-        .with_state(state)
-        .fallback_service(ServeDir::new("static"));
+/// Representation of the mixed color picked from the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorFormat {
+    Json,
+    Text,
+    Svg,
+}
+
+impl ColorFormat {
+    /// The first supported media type the client lists, JSON when there is none.
+    /// Quality values are not weighed, clients are expected to list their preference first.
+    fn from_accept(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(|media| match media.split(';').next().unwrap_or_default().trim() {
+                "application/json" | "application/*" | "*/*" => Some(ColorFormat::Json),
+                "text/plain" | "text/*" => Some(ColorFormat::Text),
+                "image/svg+xml" | "image/*" => Some(ColorFormat::Svg),
+                _ => None,
+            })
+            .unwrap_or(ColorFormat::Json)
+    }
+
+    /// Entity tag of this representation of the color, so it changes with the mix and differs per format
+    fn etag(self, color: &Color) -> String {
+        let format = match self {
+            ColorFormat::Json => "json",
+            ColorFormat::Text => "text",
+            ColorFormat::Svg => "svg",
+        };
+        format!("\"{}-{}\"", color.to_rgba_hex().trim_start_matches('#'), format)
+    }
+}
+
+/// Whether the `If-None-Match` header lists `etag` or `*`; weak tags match by their opaque part
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// SVG document of a rectangle filled with the color, labelled with its hex code in
+/// whichever of black or white reads better over it
+fn svg_swatch(color: &Color, width: u32, height: u32) -> String {
+    let hex = color.to_hex();
+    let text = if color.is_light() { "#000000" } else { "#FFFFFF" };
+    // Seven characters of the label have to fit across the width
+    let font_size = (width / 5).min(height / 2).max(1);
+
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#,
+            r#"<rect width="{width}" height="{height}" fill="{hex}"/>"#,
+            r#"<text x="50%" y="50%" fill="{text}" font-family="monospace" font-size="{font_size}" text-anchor="middle" dominant-baseline="central">{hex}</text>"#,
+            "</svg>"
+        ),
+        width = width,
+        height = height,
+        hex = hex,
+        text = text,
+        font_size = font_size,
+    )
+}
+
+/// Render the mixed color as an SVG swatch
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/color.svg",
+    tag = "mixer",
+    params(SvgQuery),
+    responses(
+        (status = 200, description = "Swatch of the mixed color", content_type = "image/svg+xml"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn color_svg(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<SvgQuery>,
+) -> Result<impl IntoResponse, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    let width = query.width.unwrap_or(SWATCH_SIZE).clamp(1, MAX_SWATCH_SIZE);
+    let height = query.height.unwrap_or(SWATCH_SIZE).clamp(1, MAX_SWATCH_SIZE);
+
+    Ok(([(CONTENT_TYPE, "image/svg+xml")], svg_swatch(&color, width, height)))
+}
+
+/// PNG image of a square filled with the color
+fn png_swatch(color: &Color, size: u32) -> Vec<u8> {
+    let (r, g, b) = color.rgb();
+    let image = RgbaImage::from_pixel(size, size, Rgba([r, g, b, color.alpha()]));
+
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .expect("encoding a PNG in memory cannot fail");
+    png.into_inner()
+}
+
+/// Render the mixed color as a PNG swatch
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/color.png",
+    tag = "mixer",
+    params(PngQuery),
+    responses(
+        (status = 200, description = "Swatch of the mixed color", content_type = "image/png"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn color_png(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<PngQuery>,
+) -> Result<impl IntoResponse, ColorMixerError> {
+    let color = session(&*state.sessions.read().await, &id)?.get_mixed_color()?;
+    let size = query.size.unwrap_or(SWATCH_SIZE).clamp(1, MAX_SWATCH_SIZE);
+
+    Ok(([(CONTENT_TYPE, "image/png")], png_swatch(&color, size)))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/color",
+    tag = "mixer",
+    params(MixQuery),
+    responses(
+        (status = 200, description = "The mixed color as JSON, hex text or SVG, by `Accept`", body = ColorResponse),
+        (status = 304, description = "The mixed color still matches `If-None-Match`"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn get_current_color(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<MixQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ColorMixerError> {
+    let sessions = state.sessions.read().await;
+    let mixer = session(&sessions, &id)?;
+    let mode = query.mode.unwrap_or(mixer.mix_mode());
+    let color = mixer.get_mixed_color_rounded(mode, query.rounding.unwrap_or(mixer.rounding()))?;
+
+    let format = ColorFormat::from_accept(&headers);
+    let etag = format.etag(&color);
+    let cache_headers = [(VARY, "accept".to_string()), (ETAG, etag.clone())];
+    if etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let body = match format {
+        ColorFormat::Json => Json(ColorResponse::from(&color)).into_response(),
+        ColorFormat::Text => color.to_hex().into_response(),
+        ColorFormat::Svg => {
+            ([(CONTENT_TYPE, "image/svg+xml")], svg_swatch(&color, SWATCH_SIZE, SWATCH_SIZE)).into_response()
+        }
+    };
+    Ok((cache_headers, body).into_response())
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/colors/distinct",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Each distinct color once, sorted by channel", body = Vec<ColorResponse>),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn distinct_colors(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<Vec<ColorResponse>>, ColorMixerError> {
+    let colors = session(&*state.sessions.read().await, &id)?.distinct_colors();
+
+    Ok(Json(colors.iter().map(ColorResponse::from).collect()))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/colors",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Each distinct color with its count", body = Vec<ColorCountResponse>),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn list_colors(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<Vec<ColorCountResponse>>, ColorMixerError> {
+    let counts = session(&*state.sessions.read().await, &id)?
+        .color_counts()
+        .into_iter()
+        .map(|(color, count)| ColorCountResponse {
+            hex: color.to_hex(),
+            rgb: color.rgb(),
+            count,
+        })
+        .collect();
+
+    Ok(Json(counts))
+}
+
+/// Download the session's distinct colors as a CSS, GIMP palette or JSON file
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/export",
+    tag = "mixer",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The colors as a file download"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn export_colors(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<ExportQuery>,
+) -> Result<impl IntoResponse, ColorMixerError> {
+    let colors = session(&*state.sessions.read().await, &id)?.color_counts();
+    let (content_type, file_name, body) = match query.format {
+        ExportFormat::Css => ("text/css; charset=utf-8", "palette.css", css_palette(&colors)),
+        ExportFormat::Gpl => ("text/plain; charset=utf-8", "palette.gpl", gpl_palette(&colors)),
+        ExportFormat::Json => ("application/json", "palette.json", json_palette(&colors)),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "palette.csv", csv_palette(&colors)),
+    };
+    let disposition = format!("attachment; filename=\"{}\"", file_name);
+
+    Ok(([(CONTENT_TYPE, content_type.to_string()), (CONTENT_DISPOSITION, disposition)], body))
+}
+
+/// CSS custom properties `--color-0`, `--color-1`, ... in order of first insertion
+fn css_palette(colors: &[(Color, u32)]) -> String {
+    let mut css = String::from(":root {\n");
+    for (index, (color, _)) in colors.iter().enumerate() {
+        css.push_str(&format!("  --color-{}: {};\n", index, color.to_hex()));
+    }
+    css.push_str("}\n");
+    css
+}
 
-    // Run it with hyper on localhost:8080
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// GIMP palette with each color named after its nearest CSS color
+fn gpl_palette(colors: &[(Color, u32)]) -> String {
+    let mut gpl = String::from("GIMP Palette\nName: Color Mixer\nColumns: 0\n#\n");
+    for (color, _) in colors {
+        let (r, g, b) = color.rgb();
+        gpl.push_str(&format!("{:3} {:3} {:3}\t{}\n", r, g, b, color.nearest_name()));
+    }
+    gpl
+}
+
+/// Download the session's distinct colors as CSV
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/export.csv",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "The colors as a CSV download", content_type = "text/csv"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn export_csv(state: State<AppState>, id: SessionId) -> Result<impl IntoResponse, ColorMixerError> {
+    let query = ApiQuery(ExportQuery { format: ExportFormat::Csv });
+    export_colors(state, id, query).await
+}
+
+/// Header of the CSV export, which the import skips with or without the `a` column
+const CSV_HEADER: &str = "hex,r,g,b,count,a";
+
+/// `hex,r,g,b,count,a` rows under a header, in order of first insertion, `a` being the opacity
+fn csv_palette(colors: &[(Color, u32)]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for (color, count) in colors {
+        let (r, g, b) = color.rgb();
+        csv.push_str(&format!("{},{},{},{},{},{}\n", color.to_hex(), r, g, b, count, color.alpha()));
+    }
+    csv
 }
 
+/// Parse `hex,r,g,b,count` rows with an optional `a` opacity column, skipping blank lines and an
+/// optional header. Without `a`, a color is as opaque as its hex. Errors name the 1-based line of
+/// the first bad row.
+fn parse_csv(csv: &str) -> Result<Vec<(Color, u32)>, ColorMixerError> {
+    let mut colors = Vec::new();
+    for (index, row) in csv.lines().enumerate() {
+        let row = row.trim();
+        let header = |header: &str| row.eq_ignore_ascii_case(header);
+        if row.is_empty() || (index == 0 && (header(CSV_HEADER) || CSV_HEADER.strip_suffix(",a").is_some_and(header))) {
+            continue;
+        }
+
+        let invalid = |message: String| ColorMixerError::InvalidCsv { line: index + 1, message };
+        let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+        let (hex, r, g, b, count, a) = match fields[..] {
+            [hex, r, g, b, count] => (hex, r, g, b, count, None),
+            [hex, r, g, b, count, a] => (hex, r, g, b, count, Some(a)),
+            _ => return Err(invalid(format!("Expected 5 or 6 fields (hex,r,g,b,count,a), got {}", fields.len()))),
+        };
+
+        let color: Color = hex.parse().map_err(|err: ColorMixerError| invalid(err.to_string()))?;
+        let channel = |value: &str| value.parse::<u8>().map_err(|_| invalid(format!("Invalid channel {:?}", value)));
+        let rgb = (channel(r)?, channel(g)?, channel(b)?);
+        if color.rgb() != rgb {
+            return Err(invalid(format!("{} does not match the channels {},{},{}", hex, r, g, b)));
+        }
+        let color = match a {
+            Some(a) => Color::with_alpha(rgb.0, rgb.1, rgb.2, channel(a)?),
+            None => color,
+        };
+        let count = match count.parse::<u32>() {
+            Ok(0) => return Err(invalid(ColorMixerError::ZeroQuantity.to_string())),
+            Ok(count) => count,
+            Err(_) => return Err(invalid(format!("Invalid count {:?}", count))),
+        };
+
+        colors.push((color, count));
+    }
+    Ok(colors)
+}
+
+/// Replace the session's colors with CSV rows, or add to them with `?append=true`, all or nothing
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/import.csv",
+    tag = "mixer",
+    params(ImportQuery),
+    request_body(content = String, content_type = "text/csv"),
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn import_csv(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    ApiQuery(query): ApiQuery<ImportQuery>,
+    body: Result<String, StringRejection>,
+) -> Result<Response, ColorMixerError> {
+    let mut colors = parse_csv(&body?)?;
+
+    // Like setting the colors, importing starts the session if needed
+    let mut sessions = state.sessions.write().await;
+    state.start_or_change(&mut sessions, &id, |mixer| {
+        if query.append {
+            colors.splice(0..0, mixer.color_counts());
+        }
+        mixer.replace_colors(colors)
+    })?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+/// JSON array in the `GET /api/colors` shape
+fn json_palette(colors: &[(Color, u32)]) -> String {
+    let counts: Vec<_> = colors
+        .iter()
+        .map(|(color, count)| ColorCountResponse {
+            hex: color.to_hex(),
+            rgb: color.rgb(),
+            count: *count,
+        })
+        .collect();
+    serde_json::to_string_pretty(&counts).expect("color counts always serialize")
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/count",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Units in total and per family", body = CountResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn count_colors(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<CountResponse>, ColorMixerError> {
+    let sessions = state.sessions.read().await;
+    let mixer = session(&sessions, &id)?;
+    Ok(Json(CountResponse {
+        total: mixer.len(),
+        families: mixer.family_counts(),
+    }))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/color/composition",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Share of each family in percent", body = Composition),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn composition(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<Composition>, ColorMixerError> {
+    Ok(Json(session(&*state.sessions.read().await, &id)?.composition()))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/stats",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Channel statistics over every unit", body = ColorStats),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn stats(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<ColorStats>, ColorMixerError> {
+    Ok(Json(session(&*state.sessions.read().await, &id)?.stats()))
+}
+
+/// Clear the session's colors, reporting how many units were removed
+#[tracing::instrument(skip_all, fields(session = %id, removed = tracing::field::Empty))]
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/clear",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Units removed", body = RemoveResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn clear_colors(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<RemoveResponse>, ColorMixerError> {
+    let mut sessions = state.sessions.write().await;
+    let removed = session_mut(&mut sessions, &id)?.clear();
+    Span::current().record("removed", removed);
+    if removed > 0 {
+        state.changed(&sessions, &id);
+    }
+    metrics::counter!("color_mixer_clears_total").increment(1);
+    tracing::debug!("Cleared the colors");
+
+    Ok(Json(RemoveResponse { removed }))
+}
+
+/// Remove every color of one family from the session, reporting how many units were removed
+#[cfg_attr(feature = "docs", utoipa::path(
+    delete,
+    path = "/api/colors/family/{family}",
+    tag = "mixer",
+    params(("family" = String, Path, description = "yellow, blue, red or green")),
+    responses(
+        (status = 200, description = "Units removed", body = RemoveResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn clear_family(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    FamilyName(family): FamilyName,
+) -> Result<Json<RemoveResponse>, ColorMixerError> {
+    let mut sessions = state.sessions.write().await;
+    let removed = session_mut(&mut sessions, &id)?.clear_family(family);
+    if removed > 0 {
+        state.changed(&sessions, &id);
+    }
+
+    Ok(Json(RemoveResponse { removed }))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/undo",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn undo(State(state): State<AppState>, SessionId(id): SessionId) -> Result<Response, ColorMixerError> {
+    let mut sessions = state.sessions.write().await;
+    session_mut(&mut sessions, &id)?.undo()?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+/// Replace the session's colors with one unit of each of the six default shades
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/reset",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn reset(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Response, ColorMixerError> {
+    // Like setting the colors, resetting starts the session if needed
+    let mut sessions = state.sessions.write().await;
+    state.start_or_change(&mut sessions, &id, ColorMixer::reset_to_default)?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/redo",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn redo(State(state): State<AppState>, SessionId(id): SessionId) -> Result<Response, ColorMixerError> {
+    let mut sessions = state.sessions.write().await;
+    session_mut(&mut sessions, &id)?.redo()?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+/// The session's recent changes with their times, oldest first
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/history",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Recent changes, oldest first", body = Vec<HistoryEntry>),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn history(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<Vec<HistoryEntry>>, ColorMixerError> {
+    Ok(Json(session(&*state.sessions.read().await, &id)?.history()))
+}
+
+/// Save the session's current colors as a named snapshot and list the snapshots
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/snapshot/{name}",
+    tag = "mixer",
+    params(("name" = String, Path, description = "Snapshot name")),
+    responses(
+        (status = 200, description = "Every snapshot name", body = SnapshotsResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn snapshot(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    SnapshotName(name): SnapshotName,
+) -> Result<Json<SnapshotsResponse>, ColorMixerError> {
+    let mut sessions = state.sessions.write().await;
+    let mixer = session_mut(&mut sessions, &id)?;
+    mixer.snapshot(name);
+    let snapshots = mixer.list_snapshots().into_iter().map(String::from).collect();
+    state.changed(&sessions, &id);
+
+    Ok(Json(SnapshotsResponse { snapshots }))
+}
+
+/// Bring back the colors of a named snapshot
+#[cfg_attr(feature = "docs", utoipa::path(
+    post,
+    path = "/api/restore/{name}",
+    tag = "mixer",
+    params(("name" = String, Path, description = "Snapshot name")),
+    responses(
+        (status = 200, description = "The mixed color", body = ColorResponse),
+        (status = 204, description = "The change left the mixer empty"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn restore(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+    SnapshotName(name): SnapshotName,
+) -> Result<Response, ColorMixerError> {
+    let mut sessions = state.sessions.write().await;
+    session_mut(&mut sessions, &id)?.restore(&name)?;
+    state.changed(&sessions, &id);
+
+    Ok(mixed_response(&sessions[&id], None))
+}
+
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/snapshots",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "Every snapshot name", body = SnapshotsResponse),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn list_snapshots(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Result<Json<SnapshotsResponse>, ColorMixerError> {
+    let sessions = state.sessions.read().await;
+    let snapshots = session(&sessions, &id)?.list_snapshots().into_iter().map(String::from).collect();
+
+    Ok(Json(SnapshotsResponse { snapshots }))
+}
+
+/// Push the session's mixed color over a WebSocket, first on connect and then after every change
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/ws",
+    tag = "mixer",
+    responses(
+        (status = 101, description = "WebSocket sending the mixed color after every change"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn watch(State(state): State<AppState>, SessionId(id): SessionId, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| push_updates(socket, state, id))
+}
+
+async fn push_updates(mut socket: WebSocket, state: AppState, id: String) {
+    // Subscribe before reading the current color so no change can slip in between
+    let mut updates = state.updates.subscribe();
+    let current = state.current_color(&id).await;
+    if socket.send(Message::Text(color_message(current.as_ref()))).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            update = next_update(&mut updates, &state, &id) => {
+                let Some(color) = update else { break };
+                if socket.send(Message::Text(color_message(color.as_ref()))).await.is_err() {
+                    break;
+                }
+            }
+            // Anything the client sends is ignored, but reading is how a disconnect shows up
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Stream the session's mixed color as Server-Sent Events, first on connect and then after every change.
+/// Subscribers that fall behind skip to the latest color instead of holding up the writers.
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "mixer",
+    responses(
+        (status = 200, description = "The mixed color after every change", content_type = "text/event-stream"),
+        (status = "4XX", description = "The request was rejected", body = ErrorBody),
+    )
+))]
+async fn events(
+    State(state): State<AppState>,
+    SessionId(id): SessionId,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    // Subscribe before reading the current color so no change can slip in between
+    let updates = state.updates.subscribe();
+    let current = state.current_color(&id).await;
+
+    let changes = stream::unfold((updates, state, id), |(mut updates, state, id)| async move {
+        let color = next_update(&mut updates, &state, &id).await?;
+        Some((color_event(color.as_ref()), (updates, state, id)))
+    });
+
+    Sse::new(stream::once(future::ready(color_event(current.as_ref()))).chain(changes)).keep_alive(KeepAlive::default())
+}
+
+/// JSON 404 for paths under `/api` that match no endpoint
+async fn api_not_found(OriginalUri(uri): OriginalUri) -> ColorMixerError {
+    ColorMixerError::RouteNotFound(uri.path().to_string())
+}
+
+/// Metrics in the Prometheus text exposition format
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "server",
+    responses((status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain"))
+))]
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render())
+}
+
+/// Liveness probe: answers as long as the server is running
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "server",
+    responses((status = 200, body = StatusResponse))
+))]
+async fn health() -> Json<StatusResponse> {
+    Json(StatusResponse { status: "ok" })
+}
+
+/// Readiness probe: `503` until the state is loaded and the server is listening.
+/// Reads only an atomic flag, never the sessions lock, so it stays fast under load.
+#[cfg_attr(feature = "docs", utoipa::path(
+    get,
+    path = "/api/ready",
+    tag = "server",
+    responses(
+        (status = 200, description = "Ready", body = StatusResponse),
+        (status = 503, description = "Still starting", body = StatusResponse),
+    )
+))]
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<StatusResponse>) {
+    if state.ready.load(Ordering::Acquire) {
+        (StatusCode::OK, Json(StatusResponse { status: "ready" }))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(StatusResponse { status: "starting" }))
+    }
+}
+
+/// OpenAPI document of every endpoint, served with the `docs` feature
+#[cfg(feature = "docs")]
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Color Mixer",
+        description = "Every `mixer` endpoint also works under `/api/{session}` for a named session, \
+                       e.g. `/api/alice/color`; the paths listed use the default session."
+    ),
+    paths(
+        health, ready, metrics,
+        add_color, get_current_color, remove_color, set_colors, set_quantity, color_png, color_svg, simulate_cvd,
+        filter_color, adjust_color, rotate_color, list_colors, distinct_colors, add_batch, clear_family, add_random,
+        sample, export_colors, export_csv, import_csv, count_colors, composition, stats, preview_color, palette, ramp,
+        clear_colors, reset, undo, redo, history, snapshot, restore, list_snapshots, watch, events,
+        random, mix_colors, mix_ratio, contrast, delta_e, gradient, blend,
+    ),
+    // Types only used in query parameters are referenced but not collected from the paths
+    components(schemas(ErrorBody, MixMode, RoundingMode, HarmonyScheme, ExportFormat, Filter, CvdKind, RampDirection))
+)]
+struct ApiDoc;
+
+/// The OpenAPI document as JSON
+#[cfg(feature = "docs")]
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Span covering one request, tagged with the ID `SetRequestIdLayer` gave it or the client sent
+fn request_span<B>(request: &axum::http::Request<B>) -> Span {
+    let id = request.headers().get("x-request-id").and_then(|id| id.to_str().ok()).unwrap_or_default();
+    tracing::info_span!("request", id, method = %request.method(), uri = %request.uri())
+}
+
+/// Read a limit from the environment variable `name`, if set and valid
+fn limit_from_env<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(max) => Some(max),
+        Err(_) => {
+            tracing::warn!("Invalid {} value {:?}, using the default limit", name, value);
+            None
+        }
+    }
+}
+
+/// Gzip or brotli compression, as the client's `Accept-Encoding` allows, of larger responses; PNGs are
+/// compressed already and event streams have to reach the client as they are written
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(COMPRESSION_MIN_BYTES)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE),
+    )
+}
+
+/// CORS policy allowing the origins listed in `origins`, comma-separated, or any origin when unset
+fn cors_layer(origins: Option<String>) -> CorsLayer {
+    let allow_origin = match origins {
+        Some(origins) => {
+            let origins = origins.split(',').map(str::trim).filter(|origin| !origin.is_empty());
+            AllowOrigin::list(origins.filter_map(|origin| match HeaderValue::from_str(origin) {
+                Ok(origin) => Some(origin),
+                Err(_) => {
+                    tracing::warn!("Ignoring invalid origin {:?} in ALLOWED_ORIGINS", origin);
+                    None
+                }
+            }))
+        }
+        None => AllowOrigin::any(),
+    };
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_headers([CONTENT_TYPE, AUTHORIZATION, IF_NONE_MATCH])
+        .expose_headers([ETAG])
+}
+
+/// Let requests that change nothing through, and the rest only with `Authorization: Bearer <token>`
+/// when a token is configured
+async fn require_token(
+    State(token): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ColorMixerError> {
+    let read_only = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if let Some(token) = token
+        && !read_only
+    {
+        let given = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| tokens_match(given, &token)) {
+            return Err(ColorMixerError::Unauthorized);
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+/// Turn away clients that add colors faster than the limiter allows, with the seconds to wait in `Retry-After`
+async fn rate_limit(
+    State(AddLimit { limiter, trust_proxy }): State<AddLimit>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let forwarded = trust_proxy.then(|| forwarded_for(request.headers())).flatten();
+    let client = forwarded.unwrap_or(peer.ip());
+    if let Err(not_until) = limiter.check_key(&client) {
+        let wait = not_until.wait_time_from(DefaultClock::default().now());
+        // Round up, so a client that waits as told is let through
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        return ([(RETRY_AFTER, retry_after.to_string())], ColorMixerError::RateLimited(retry_after)).into_response();
+    }
+    next.run(request).await
+}
+
+/// The client address the nearest proxy appended last to `X-Forwarded-For`, if any; earlier entries come
+/// from the client and can be anything
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    let forwarded = headers.get("x-forwarded-for")?.to_str().ok()?;
+    forwarded.rsplit(',').next()?.trim().parse().ok()
+}
+
+/// Compare tokens in time depending only on their lengths, so timing gives away no prefix
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Address to listen on from the `HOST` and `PORT` values, defaulting to `127.0.0.1:8080`
+fn bind_address(host: Option<String>, port: Option<String>) -> Result<(String, u16), String> {
+    let host = host.unwrap_or_else(|| DEFAULT_HOST.to_string());
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| format!("Invalid PORT value {:?}, expected 0 to 65535", port))?,
+        None => DEFAULT_PORT,
+    };
+    Ok((host, port))
+}
+
+/// Load the saved sessions, starting with none if the file is missing or unreadable
+fn load_state(path: &Path) -> Sessions {
+    if !path.exists() {
+        tracing::info!("No state file at {}, starting with no sessions", path.display());
+        return Sessions::new();
+    }
+
+    let sessions = std::fs::read_to_string(path)
+        .and_then(|json| Ok(serde_json::from_str(&json)?));
+    sessions.unwrap_or_else(|err| {
+        tracing::warn!("Failed to load {}: {}, starting with no sessions", path.display(), err);
+        Sessions::new()
+    })
+}
+
+/// Save the sessions to `path` whenever `changed` asks for it, `SAVE_DELAY` later, and one last
+/// time when `done` fires, after the server stopped taking requests
+async fn save_changes(state: AppState, path: Arc<Path>, mut done: oneshot::Receiver<()>) {
+    loop {
+        tokio::select! {
+            _ = state.save.notified() => tokio::time::sleep(SAVE_DELAY).await,
+            _ = &mut done => break,
+        }
+        state.save_to(&path).await;
+    }
+
+    if state.save_to(&path).await {
+        tracing::info!("Saved the sessions to {}", path.display());
+    }
+}
+
+/// Write serialized sessions to `path`, replacing the file atomically
+fn write_state(path: &Path, json: &str) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    std::fs::write(&temp, json)?;
+    std::fs::rename(&temp, path)
+}
+
+/// Resolve on Ctrl+C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Every route with its middleware, adds limited by `limit` and changes guarded by `api_token` when set
+fn app(state: AppState, limit: AddLimit, api_token: Option<Arc<str>>) -> Router {
+    // Routes that work on a session's mixer, mounted both unscoped and under a session ID
+    let session_routes = Router::new()
+        .route("/color", post(add_color).route_layer(middleware::from_fn_with_state(limit, rate_limit)))
+        .route("/color", get(get_current_color))
+        .route("/color", delete(remove_color))
+        .route("/color", put(set_colors))
+        .route("/color", patch(set_quantity))
+        .route("/color.png", get(color_png))
+        .route("/color.svg", get(color_svg))
+        .route("/color/cvd", get(simulate_cvd))
+        .route("/color/composition", get(composition))
+        .route("/color/filter", get(filter_color))
+        .route("/color/adjust", post(adjust_color))
+        .route("/color/rotate", post(rotate_color))
+        .route("/colors", get(list_colors))
+        .route("/colors/batch", post(add_batch))
+        .route("/colors/distinct", get(distinct_colors))
+        .route("/colors/family/{family}", delete(clear_family))
+        .route("/random", post(add_random))
+        .route("/sample", get(sample))
+        .route("/export", get(export_colors))
+        .route("/export.csv", get(export_csv))
+        .route("/import.csv", post(import_csv))
+        .route("/count", get(count_colors))
+        .route("/stats", get(stats))
+        .route("/preview", post(preview_color))
+        .route("/palette", get(palette))
+        .route("/ramp", get(ramp))
+        .route("/clear", post(clear_colors))
+        .route("/reset", post(reset))
+        .route("/undo", post(undo))
+        .route("/redo", post(redo))
+        .route("/history", get(history))
+        .route("/snapshot/{name}", post(snapshot))
+        .route("/restore/{name}", post(restore))
+        .route("/snapshots", get(list_snapshots))
+        .route("/ws", get(watch))
+        .route("/events", get(events));
+
+    // Unknown API paths get a JSON error instead of falling through to the static files
+    let api_routes = Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/mix", post(mix_colors))
+        .route("/mix/ratio", post(mix_ratio))
+        .route("/random", get(random))
+        .route("/contrast", post(contrast))
+        .route("/deltae", post(delta_e))
+        .route("/gradient", post(gradient))
+        .route("/blend", post(blend))
+        .merge(session_routes.clone())
+        .nest("/{session}", session_routes)
+        .fallback(api_not_found);
+    #[cfg(feature = "docs")]
+    let api_routes = api_routes.route("/openapi.json", get(openapi));
+
+    // Build our application with routes
+    let app = Router::new().route("/metrics", get(metrics));
+    #[cfg(feature = "docs")]
+    // Swagger UI from assets bundled into the binary, reading the document served above
+    let app = app.merge(SwaggerUi::new("/swagger").config(SwaggerConfig::from("/api/openapi.json")));
+    app
+        .nest("/api", api_routes)
+        .with_state(state)
+        .fallback_service(ServeDir::new("static"))
+        .layer(middleware::from_fn_with_state(api_token, require_token))
+        .layer(DefaultBodyLimit::max(limit_from_env("MAX_BODY_BYTES").unwrap_or(DEFAULT_MAX_BODY_BYTES)))
+        .layer(cors_layer(std::env::var("ALLOWED_ORIGINS").ok()))
+        .layer(compression_layer())
+        // Outermost, so every span and log line of a request carries its ID; the ID is echoed in the response
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+#[tokio::main]
+async fn main() {
+    // Log at the levels in RUST_LOG, errors only when unset
+    tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env()).init();
+
+    // Create shared state, restoring the saved sessions if there are any
+    let state_file: Option<Arc<Path>> = std::env::var_os("STATE_FILE").map(|path| Path::new(&path).into());
+    let mut sessions = state_file.as_deref().map(load_state).unwrap_or_default();
+    let max_colors = limit_from_env("MAX_COLORS").unwrap_or_else(|| ColorMixer::default().max_colors());
+    let max_per_request = limit_from_env("MAX_PER_REQUEST").unwrap_or_else(|| ColorMixer::default().max_per_request());
+    let max_per_color = limit_from_env("MAX_PER_COLOR");
+    let max_undo = limit_from_env("MAX_UNDO").unwrap_or_else(|| ColorMixer::default().max_undo());
+    for (id, mixer) in &mut sessions {
+        if let Err(err) = mixer.set_max_colors(max_colors) {
+            tracing::warn!("Keeping the saved limit of session {}: {}", id, err);
+        }
+        mixer.set_max_per_request(max_per_request);
+        mixer.set_max_per_color(max_per_color);
+        mixer.set_max_undo(max_undo);
+    }
+    let (stop, shutdown) = watch::channel(false);
+    let recorder = PrometheusBuilder::new()
+        .install_recorder()
+        .expect("no other metrics recorder is installed");
+    let state = AppState {
+        max_colors,
+        max_per_request,
+        max_per_color,
+        max_undo,
+        state_file,
+        ..AppState::new(sessions, recorder, shutdown)
+    };
+    let default_mixer = state.new_mixer();
+    state.sessions.write().await.entry(DEFAULT_SESSION.to_string()).or_insert(default_mixer);
+    let units: usize = state.sessions.read().await.values().map(ColorMixer::len).sum();
+    metrics::gauge!("color_mixer_colors").set(units as f64);
+    let ready_flag = state.ready.clone();
+    // Saving runs alongside the server, so no change waits for the disk
+    let (saved, save_done) = oneshot::channel();
+    let saver = state
+        .state_file
+        .clone()
+        .map(|path| tokio::spawn(save_changes(state.clone(), path, save_done)));
+    // An empty token would be trivially guessed, so it counts as unset
+    let api_token: Option<Arc<str>> = std::env::var("API_TOKEN").ok().filter(|token| !token.is_empty()).map(Into::into);
+    if api_token.is_none() {
+        tracing::info!("API_TOKEN is not set, changes need no authentication");
+    }
+    let rate = limit_from_env("RATE_LIMIT").unwrap_or(DEFAULT_RATE_LIMIT);
+    let limiter: AddLimiter = Arc::new(RateLimiter::keyed(Quota::per_second(rate)));
+    // Forget clients whose bucket has refilled, so the limiter does not grow with every address ever seen
+    let cleanup = limiter.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RATE_LIMIT_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            cleanup.retain_recent();
+            cleanup.shrink_to_fit();
+        }
+    });
+
+    // Only a proxy in front can vouch for `X-Forwarded-For`, so the header is ignored unless told otherwise
+    let trust_proxy = std::env::var("TRUST_PROXY").is_ok_and(|value| matches!(value.as_str(), "1" | "true"));
+    let app = app(state, AddLimit { limiter, trust_proxy }, api_token);
+
+    // Run it with hyper on HOST:PORT
+    let (host, port) = match bind_address(std::env::var("HOST").ok(), std::env::var("PORT").ok()) {
+        Ok(address) => address,
+        Err(err) => {
+            tracing::error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let listener = match tokio::net::TcpListener::bind((host.as_str(), port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            tracing::error!("Failed to listen on {}:{}: {}", host, port, err);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("Listening on {}:{}", host, port);
+    ready_flag.store(true, Ordering::Release);
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            tracing::info!("Shutting down, waiting for open requests to finish");
+            ready_flag.store(false, Ordering::Release);
+            // Nobody may be watching, and then there is nothing to end
+            let _ = stop.send(true);
+        })
+        .await
+        .unwrap();
+
+    // In-flight requests are done, so the last save sees every change
+    if let Some(saver) = saver {
+        let _ = saved.send(());
+        let _ = saver.await;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::connect_info::MockConnectInfo;
+    use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    /// State with no sessions yet and no state file
+    fn empty_state() -> AppState {
+        let (_, shutdown) = watch::channel(false);
+        AppState::new(Sessions::new(), PrometheusBuilder::new().build_recorder().handle(), shutdown)
+    }
+
+    /// `state` with the default session added, as `main` does
+    fn with_default_session(state: AppState) -> AppState {
+        let mixer = state.new_mixer();
+        state.sessions.try_write().unwrap().insert(DEFAULT_SESSION.to_string(), mixer);
+        state
+    }
+
+    fn test_state() -> AppState {
+        with_default_session(empty_state())
+    }
+
+    /// App serving `state` with no token and no practical rate limit
+    fn app_with(state: AppState) -> Router {
+        serve(state, add_limit(NonZeroU32::MAX, false), None)
+    }
+
+    /// Limit of `rate` adds per second per client, trusting `X-Forwarded-For` if `trust_proxy`
+    fn add_limit(rate: NonZeroU32, trust_proxy: bool) -> AddLimit {
+        AddLimit { limiter: Arc::new(RateLimiter::keyed(Quota::per_second(rate))), trust_proxy }
+    }
+
+    /// App serving `state` to a client at 127.0.0.1
+    fn serve(state: AppState, limit: AddLimit, api_token: Option<Arc<str>>) -> Router {
+        app(state, limit, api_token).layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))))
+    }
+
+    fn test_app() -> Router {
+        app_with(test_state())
+    }
+
+    /// Request with a JSON body, or none when `body` is `Null`
+    fn request(method: Method, uri: &str, body: Value) -> Request {
+        let builder = Request::builder().method(method).uri(uri);
+        match body {
+            Value::Null => builder.body(Body::empty()),
+            body => builder.header(CONTENT_TYPE, "application/json").body(Body::from(body.to_string())),
+        }
+        .unwrap()
+    }
+
+    /// Send a request to the app and return the status, headers and raw body
+    async fn send_raw(app: &Router, request: Request) -> (StatusCode, HeaderMap, String) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Send a request to the app and return the status with the JSON body, `Null` when empty
+    async fn send(app: &Router, method: Method, uri: &str, body: Value) -> (StatusCode, Value) {
+        let response = app.clone().oneshot(request(method, uri, body)).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = if bytes.is_empty() { Value::Null } else { serde_json::from_slice(&bytes).unwrap() };
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn transparent_units_are_added_and_mixed() {
+        let app = test_app();
+        let transparent = json!({ "color": "red", "alpha": 0, "quantity": 2 });
+        let (status, body) = send(&app, Method::POST, "/api/color", transparent).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rgba"], "#FF000000");
+
+        let (_, colors) = send(&app, Method::GET, "/api/colors", Value::Null).await;
+        assert_eq!(colors[0]["count"], 2);
+    }
+
+    #[tokio::test]
+    async fn change_leaving_the_mixer_empty_is_no_content() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "blue" })).await;
+        assert_eq!(send(&app, Method::POST, "/api/undo", Value::Null).await, (StatusCode::NO_CONTENT, Value::Null));
+        assert_eq!(send(&app, Method::PUT, "/api/color", json!([])).await, (StatusCode::NO_CONTENT, Value::Null));
+    }
+
+    #[tokio::test]
+    async fn changes_are_saved_in_the_background() {
+        let path: Arc<Path> = std::env::temp_dir().join(format!("color_mixer_{}.json", std::process::id())).into();
+        let state = AppState { state_file: Some(path.clone()), ..test_state() };
+        let (saved, done) = oneshot::channel();
+        let saver = tokio::spawn(save_changes(state.clone(), path.clone(), done));
+
+        let app = app_with(state);
+        send(&app, Method::POST, "/api/color", json!({ "color": "red", "quantity": 3 })).await;
+        saved.send(()).unwrap();
+        saver.await.unwrap();
+
+        let sessions: Sessions = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sessions[DEFAULT_SESSION].len(), 3);
+    }
+
+    /// The color of a `ColorResponse` body
+    fn response_color(body: &Value) -> Color {
+        body["color"].as_str().unwrap().parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn ratio_mix_follows_the_larger_part() {
+        let app = test_app();
+        let (yellow, blue): (Color, Color) = ("yellow".parse().unwrap(), "blue".parse().unwrap());
+
+        let (status, body) = send(&app, Method::POST, "/api/mix/ratio", json!({ "yellow": 90, "blue": 10 })).await;
+        assert_eq!(status, StatusCode::OK);
+        let mixed = response_color(&body);
+        assert!(mixed.delta_e(&yellow) < mixed.delta_e(&blue), "yellow should dominate, got {mixed}");
+
+        let (_, body) = send(&app, Method::POST, "/api/mix/ratio", json!({ "yellow": 50, "blue": 50 })).await;
+        let (r, g, b) = response_color(&body).rgb();
+        assert!(g > r && g > b, "an even mix should be green, got ({r}, {g}, {b})");
+    }
+
+    #[tokio::test]
+    async fn ratio_mix_rejects_all_zero_parts() {
+        let app = test_app();
+        let (status, body) = send(&app, Method::POST, "/api/mix/ratio", json!({ "yellow": 0, "blue": 0 })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "NO_COLORS");
+    }
+
+    #[tokio::test]
+    async fn unknown_api_paths_get_a_json_404() {
+        let (status, body) = send(&test_app(), Method::GET, "/api/colr", Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "ROUTE_NOT_FOUND");
+        assert_eq!(body["error"], "not found");
+        assert_eq!(body["path"], "/api/colr");
+    }
+
+    #[tokio::test]
+    async fn other_paths_serve_the_static_files() {
+        let response = test_app().oneshot(request(Method::GET, "/index.html", Value::Null)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+    }
+
+    /// `POST /api/color` for one red unit, claiming to come from `forwarded`
+    fn add_from(forwarded: &str) -> Request {
+        let mut request = request(Method::POST, "/api/color", json!({ "color": "red" }));
+        request.headers_mut().insert("x-forwarded-for", forwarded.parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn forwarded_for_is_ignored_unless_trusted() {
+        let app = serve(test_state(), add_limit(NonZeroU32::MIN, false), None);
+        let first = app.clone().oneshot(add_from("10.0.0.1")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = app.oneshot(add_from("10.0.0.2")).await.unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn trusted_proxy_hop_tells_clients_apart() {
+        let app = serve(test_state(), add_limit(NonZeroU32::MIN, true), None);
+        let first = app.clone().oneshot(add_from("10.0.0.9, 10.0.0.1")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        // Only the proxy's own entry counts, whatever the client put before it
+        let spoofed = app.clone().oneshot(add_from("10.0.0.8, 10.0.0.1")).await.unwrap();
+        assert_eq!(spoofed.status(), StatusCode::TOO_MANY_REQUESTS);
+        let other = app.oneshot(add_from("10.0.0.2")).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn add_honours_the_shade() {
+        let app = test_app();
+        let (status, body) = send(&app, Method::POST, "/api/color", json!({ "color": "blue", "shade": "dark" })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["color"], "#00205B");
+        assert_eq!(body["name"], "dark-blue");
+
+        let invalid = json!({ "color": "teal", "shade": "light" });
+        let (status, body) = send(&app, Method::POST, "/api/color", invalid).await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("UNSUPPORTED_SHADE")));
+    }
+
+    #[tokio::test]
+    async fn delete_reports_the_units_removed() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red", "quantity": 3 })).await;
+        let (_, body) = send(&app, Method::DELETE, "/api/color", json!({ "color": "red", "quantity": 2 })).await;
+        assert_eq!(body, json!({ "removed": 2 }));
+        let (_, body) = send(&app, Method::DELETE, "/api/color", json!({ "color": "red", "quantity": 5 })).await;
+        assert_eq!(body, json!({ "removed": 1 }));
+        let (status, body) = send(&app, Method::DELETE, "/api/color", json!({ "color": "red" })).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("COLOR_NOT_FOUND")));
+    }
+
+    #[tokio::test]
+    async fn colors_are_listed_with_their_counts() {
+        let app = test_app();
+        assert_eq!(send(&app, Method::GET, "/api/colors", Value::Null).await.1, json!([]));
+        send(&app, Method::POST, "/api/color", json!({ "color": "red", "quantity": 2 })).await;
+        send(&app, Method::POST, "/api/color", json!({ "color": "blue" })).await;
+        let (_, colors) = send(&app, Method::GET, "/api/colors", Value::Null).await;
+        assert_eq!(colors, json!([
+            { "hex": "#FF0000", "rgb": [255, 0, 0], "count": 2 },
+            { "hex": "#0047AB", "rgb": [0, 71, 171], "count": 1 },
+        ]));
+    }
+
+    #[tokio::test]
+    async fn errors_carry_a_code_and_message() {
+        let app = test_app();
+        let (status, body) = send(&app, Method::POST, "/api/color", json!({ "color": "mauve" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "UNSUPPORTED_COLOR");
+        assert!(body["message"].as_str().unwrap().contains("mauve"));
+
+        let (status, body) = send(&app, Method::GET, "/api/color", Value::Null).await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("NO_COLORS")));
+        let (status, body) = send(&app, Method::POST, "/api/color", json!({ "color": "red", "quantity": 0 })).await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("ZERO_QUANTITY")));
+    }
+
+    #[tokio::test]
+    async fn stateless_mixes_leave_the_mixer_alone() {
+        let app = test_app();
+        let mix = json!({ "colors": [{ "color": "red" }, { "color": "mauve" }] });
+        let (_, body) = send(&app, Method::POST, "/api/mix", mix).await;
+        assert!(body["message"].as_str().unwrap().starts_with("Entry 1: "), "{body}");
+
+        let mix = json!({ "colors": [{ "color": "red", "quantity": 2 }] });
+        let (status, body) = send(&app, Method::POST, "/api/mix", mix).await;
+        assert_eq!((status, &body["color"]), (StatusCode::OK, &json!("#FF0000")));
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.1["total"], 0);
+    }
+
+    #[tokio::test]
+    async fn count_reports_the_families() {
+        let app = test_app();
+        let batch = json!([{ "color": "yellow", "quantity": 2 }, { "color": "blue" }, { "color": "teal" }]);
+        send(&app, Method::POST, "/api/colors/batch", batch).await;
+        let (_, count) = send(&app, Method::GET, "/api/count", Value::Null).await;
+        assert_eq!(count, json!({ "total": 4, "yellow": 2, "blue": 1, "red": 0, "green": 0 }));
+    }
+
+    #[tokio::test]
+    async fn contrast_reports_the_wcag_levels() {
+        let contrast = json!({ "foreground": "black", "background": "#FFFFFF" });
+        let (_, body) = send(&test_app(), Method::POST, "/api/contrast", contrast).await;
+        assert_eq!(body, json!({ "ratio": 21.0, "passes_aa": true, "passes_aaa": true }));
+    }
+
+    #[tokio::test]
+    async fn gradient_endpoint_checks_the_steps() {
+        let app = test_app();
+        let gradient = json!({ "from": "black", "to": "white", "steps": 3 });
+        let (_, body) = send(&app, Method::POST, "/api/gradient", gradient).await;
+        let hexes: Vec<&Value> = body.as_array().unwrap().iter().map(|color| &color["color"]).collect();
+        assert_eq!(hexes, ["#000000", "#808080", "#FFFFFF"]);
+        let gradient = json!({ "from": "black", "to": "white", "steps": 1 });
+        assert_eq!(send(&app, Method::POST, "/api/gradient", gradient).await.1["code"], "INVALID_STEPS");
+    }
+
+    #[tokio::test]
+    async fn blend_endpoint_blends_the_top_color() {
+        let blend = json!({ "base": "#808080", "top": "red", "mode": "multiply" });
+        assert_eq!(send(&test_app(), Method::POST, "/api/blend", blend).await.1["color"], "#800000");
+    }
+
+    #[tokio::test]
+    async fn sessions_are_kept_apart() {
+        let app = test_app();
+        let (status, body) = send(&app, Method::GET, "/api/alice/color", Value::Null).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("SESSION_NOT_FOUND")));
+
+        send(&app, Method::POST, "/api/alice/color", json!({ "color": "red" })).await;
+        send(&app, Method::POST, "/api/bob/color", json!({ "color": "blue", "quantity": 2 })).await;
+        assert_eq!(send(&app, Method::GET, "/api/alice/color", Value::Null).await.1["color"], "#FF0000");
+        assert_eq!(send(&app, Method::GET, "/api/bob/color", Value::Null).await.1["color"], "#0047AB");
+        assert_eq!(send(&app, Method::GET, "/api/alice/count", Value::Null).await.1["total"], 1);
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.1["total"], 0);
+    }
+
+    #[tokio::test]
+    async fn rejected_first_changes_start_no_session() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/bob/color", json!({ "color": "mauve" })).await;
+        let (status, body) = send(&app, Method::GET, "/api/bob/color", Value::Null).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("SESSION_NOT_FOUND")));
+
+        send(&app, Method::POST, "/api/bob/colors/batch", json!([{ "color": "red", "quantity": 0 }])).await;
+        send(&app, Method::POST, "/api/bob/random?count=1000", Value::Null).await;
+        let bad = Request::builder().method(Method::POST).uri("/api/bob/import.csv").body(Body::from("x")).unwrap();
+        assert_eq!(send_raw(&app, bad).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(send(&app, Method::GET, "/api/bob/count", Value::Null).await.1["code"], "SESSION_NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn route_names_are_not_session_ids() {
+        let app = test_app();
+        for (method, uri) in [(Method::POST, "/api/clear/color"), (Method::GET, "/api/undo/colors")] {
+            let (status, body) = send(&app, method, uri, json!({ "color": "red" })).await;
+            assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("INVALID_PATH")), "{uri}");
+        }
+        // Without a session of that name, the unscoped route keeps its meaning
+        let (_, body) = send(&app, Method::POST, "/api/restore/color", Value::Null).await;
+        assert_eq!(body["code"], "SNAPSHOT_NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn color_follows_the_accept_header() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+        let accepting = |accept: &'static str| {
+            let mut request = request(Method::GET, "/api/color", Value::Null);
+            request.headers_mut().insert(ACCEPT, accept.parse().unwrap());
+            request
+        };
+        assert_eq!(send_raw(&app, accepting("text/plain")).await.2, "#FF0000");
+        let (_, headers, svg) = send_raw(&app, accepting("image/svg+xml")).await;
+        assert_eq!(headers[CONTENT_TYPE], "image/svg+xml");
+        assert!(svg.contains("#FF0000"));
+        let (_, headers, _) = send_raw(&app, accepting("application/json")).await;
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+    }
+
+    #[tokio::test]
+    async fn png_swatch_is_filled_with_the_mixed_color() {
+        let app = test_app();
+        assert_eq!(send(&app, Method::GET, "/api/color.png", Value::Null).await.1["code"], "NO_COLORS");
+        send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+
+        let response = app.oneshot(request(Method::GET, "/api/color.png?size=2", Value::Null)).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+        let png = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(image.get_pixel(1, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[tokio::test]
+    async fn svg_swatch_is_labelled_with_the_hex() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+        let (status, headers, svg) = send_raw(&app, request(Method::GET, "/api/color.svg", Value::Null)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[CONTENT_TYPE], "image/svg+xml");
+        assert!(svg.contains(">#FF0000<"), "{svg}");
+    }
+
+    #[tokio::test]
+    async fn rgb_channels_stand_in_for_a_name() {
+        let app = test_app();
+        let (_, body) = send(&app, Method::POST, "/api/preview", json!({ "rgb": [255, 0, 0] })).await;
+        assert_eq!(body["color"], "#FF0000");
+        let both = json!({ "color": "red", "rgb": [255, 0, 0] });
+        assert_eq!(send(&app, Method::POST, "/api/preview", both).await.1["code"], "INVALID_BODY");
+        let shaded = json!({ "rgb": [255, 0, 0], "shade": "dark" });
+        assert_eq!(send(&app, Method::POST, "/api/preview", shaded).await.1["code"], "UNSUPPORTED_SHADE");
+    }
+
+    #[tokio::test]
+    async fn clear_reports_the_units_removed() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red", "quantity": 3 })).await;
+        assert_eq!(send(&app, Method::POST, "/api/clear", Value::Null).await.1, json!({ "removed": 3 }));
+        assert_eq!(send(&app, Method::POST, "/api/clear", Value::Null).await.1, json!({ "removed": 0 }));
+    }
+
+    #[tokio::test]
+    async fn probes_report_health_and_readiness() {
+        let state = test_state();
+        let app = app_with(state.clone());
+        let healthy = (StatusCode::OK, json!({ "status": "ok" }));
+        assert_eq!(send(&app, Method::GET, "/api/health", Value::Null).await, healthy);
+        let starting = (StatusCode::SERVICE_UNAVAILABLE, json!({ "status": "starting" }));
+        assert_eq!(send(&app, Method::GET, "/api/ready", Value::Null).await, starting);
+        state.ready.store(true, Ordering::Relaxed);
+        let ready = (StatusCode::OK, json!({ "status": "ready" }));
+        assert_eq!(send(&app, Method::GET, "/api/ready", Value::Null).await, ready);
+    }
+
+    #[tokio::test]
+    async fn limits_reject_adds() {
+        let state = AppState { max_colors: 5, max_per_request: 4, ..empty_state() };
+        let app = app_with(with_default_session(state));
+        let red = |quantity: u32| json!({ "color": "red", "quantity": quantity });
+        assert_eq!(send(&app, Method::POST, "/api/color", red(5)).await.1["code"], "QUANTITY_TOO_LARGE");
+        assert_eq!(send(&app, Method::POST, "/api/preview", red(5)).await.1["code"], "QUANTITY_TOO_LARGE");
+        assert_eq!(send(&app, Method::POST, "/api/color", red(3)).await.0, StatusCode::OK);
+        let blue = json!({ "color": "blue", "quantity": 3 });
+        assert_eq!(send(&app, Method::POST, "/api/color", blue.clone()).await.1["code"], "MAX_COLORS_REACHED");
+        assert_eq!(send(&app, Method::POST, "/api/preview", blue).await.1["code"], "MAX_COLORS_REACHED");
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.1["total"], 3);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected() {
+        let colors: Vec<Value> = (0..DEFAULT_MAX_BODY_BYTES / 10).map(|_| json!({ "color": "red" })).collect();
+        let (status, body) = send(&test_app(), Method::POST, "/api/mix", json!({ "colors": colors })).await;
+        assert_eq!((status, &body["code"]), (StatusCode::PAYLOAD_TOO_LARGE, &json!("BODY_TOO_LARGE")));
+    }
+
+    #[tokio::test]
+    async fn batch_errors_name_the_entry() {
+        let app = test_app();
+        let batch = json!([{ "color": "red" }, { "color": "mauve" }]);
+        let (status, body) = send(&app, Method::POST, "/api/colors/batch", batch).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().starts_with("Entry 1: "), "{body}");
+        assert_eq!(send(&app, Method::GET, "/api/colors", Value::Null).await.1, json!([]));
+
+        let batch = json!([{ "color": "red", "quantity": 2 }, { "color": "blue" }]);
+        assert_eq!(send(&app, Method::POST, "/api/colors/batch", batch).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.1["total"], 3);
+    }
+
+    #[tokio::test]
+    async fn palettes_export_for_design_tools() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "yellow", "shade": "dark" })).await;
+        let (status, headers, css) = send_raw(&app, request(Method::GET, "/api/export?format=css", Value::Null)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers[CONTENT_DISPOSITION].to_str().unwrap().contains("palette.css"));
+        assert!(css.contains("--color-0: #CCBB00;"), "{css}");
+        let (_, _, gpl) = send_raw(&app, request(Method::GET, "/api/export?format=gpl", Value::Null)).await;
+        assert!(gpl.starts_with("GIMP Palette"), "{gpl}");
+    }
+
+    #[tokio::test]
+    async fn csv_export_imports_back() {
+        let app = test_app();
+        let translucent = json!({ "color": "red", "alpha": 128 });
+        let batch = json!([{ "color": "yellow", "quantity": 2 }, { "color": "#123456" }, translucent]);
+        send(&app, Method::POST, "/api/colors/batch", batch).await;
+        let (status, _, csv) = send_raw(&app, request(Method::GET, "/api/export.csv", Value::Null)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(csv.lines().next(), Some("hex,r,g,b,count,a"));
+        assert_eq!(csv.lines().last(), Some("#FF0000,255,0,0,1,128"));
+
+        let import = Request::builder().method(Method::POST).uri("/api/copy/import.csv").body(Body::from(csv.clone()));
+        assert_eq!(send_raw(&app, import.unwrap()).await.0, StatusCode::OK);
+        let exported = send_raw(&app, request(Method::GET, "/api/copy/export.csv", Value::Null)).await.2;
+        assert_eq!(exported, csv);
+        let (_, original) = send(&app, Method::GET, "/api/colors", Value::Null).await;
+        assert_eq!(send(&app, Method::GET, "/api/copy/colors", Value::Null).await.1, original);
+
+        // Rows without the opacity column are opaque
+        let opaque = Body::from("hex,r,g,b,count\n#FF0000,255,0,0,2");
+        let opaque = Request::builder().method(Method::POST).uri("/api/import.csv").body(opaque).unwrap();
+        assert_eq!(send_raw(&app, opaque).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Method::GET, "/api/color", Value::Null).await.1["rgba"], "#FF0000FF");
+
+        let bad = Body::from("hex,r,g,b,count\n#FF0000,0,0,0,1");
+        let bad = Request::builder().method(Method::POST).uri("/api/import.csv").body(bad).unwrap();
+        let (status, _, body) = send_raw(&app, bad).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("INVALID_CSV") && body.contains("Line 2"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn csv_bodies_are_rejected_as_json_errors() {
+        let app = test_app();
+        let import = |body: Body| Request::builder().method(Method::POST).uri("/api/import.csv").body(body).unwrap();
+        let (status, _, body) = send_raw(&app, import(Body::from(vec![0xFF, 0xFE]))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("INVALID_BODY"), "{body}");
+
+        let rows = "#FF0000,255,0,0,1\n".repeat(DEFAULT_MAX_BODY_BYTES / 10);
+        let (status, _, body) = send_raw(&app, import(Body::from(rows))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(body.contains("BODY_TOO_LARGE"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn snapshots_restore_the_colors() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+        let (_, body) = send(&app, Method::POST, "/api/snapshot/reds", Value::Null).await;
+        assert_eq!(body, json!({ "snapshots": ["reds"] }));
+        send(&app, Method::POST, "/api/color", json!({ "color": "blue" })).await;
+        let (_, restored) = send(&app, Method::POST, "/api/restore/reds", Value::Null).await;
+        assert_eq!(restored["color"], "#FF0000");
+        let (status, body) = send(&app, Method::POST, "/api/restore/blues", Value::Null).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("SNAPSHOT_NOT_FOUND")));
+    }
+
+    #[tokio::test]
+    async fn undo_and_redo_round_trip() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+        send(&app, Method::POST, "/api/color", json!({ "color": "yellow" })).await;
+        let (_, mixed) = send(&app, Method::GET, "/api/color", Value::Null).await;
+
+        let (status, undone) = send(&app, Method::POST, "/api/undo", Value::Null).await;
+        assert_eq!((status, &undone["color"]), (StatusCode::OK, &json!("#FF0000")));
+        let (status, redone) = send(&app, Method::POST, "/api/redo", Value::Null).await;
+        assert_eq!((status, redone), (StatusCode::OK, mixed));
+        assert_eq!(send(&app, Method::POST, "/api/redo", Value::Null).await.1["code"], "NOTHING_TO_REDO");
+    }
+
+    #[tokio::test]
+    async fn history_entries_carry_their_time() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+        send(&app, Method::POST, "/api/undo", Value::Null).await;
+        let (_, history) = send(&app, Method::GET, "/api/history", Value::Null).await;
+        let ops: Vec<&str> = history.as_array().unwrap().iter().map(|entry| entry["op"].as_str().unwrap()).collect();
+        assert_eq!(ops, ["add", "undo"]);
+        assert!(history[0]["at"].as_str().unwrap().parse::<chrono::DateTime<chrono::Utc>>().is_ok());
+    }
+
+    #[tokio::test]
+    async fn patch_sets_the_count() {
+        let app = test_app();
+        let (status, _) = send(&app, Method::PATCH, "/api/color", json!({ "color": "red", "quantity": 5 })).await;
+        assert_eq!(status, StatusCode::OK);
+        send(&app, Method::PATCH, "/api/color", json!({ "color": "red", "quantity": 2 })).await;
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.1["total"], 2);
+    }
+
+    #[tokio::test]
+    async fn changes_need_the_token_when_one_is_set() {
+        let app = serve(test_state(), add_limit(NonZeroU32::MAX, false), Some("secret".into()));
+        let (status, body) = send(&app, Method::POST, "/api/color", json!({ "color": "red" })).await;
+        assert_eq!((status, &body["code"]), (StatusCode::UNAUTHORIZED, &json!("UNAUTHORIZED")));
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.0, StatusCode::OK);
+
+        let mut wrong = request(Method::POST, "/api/color", json!({ "color": "red" }));
+        wrong.headers_mut().insert(AUTHORIZATION, "Bearer secreT".parse().unwrap());
+        assert_eq!(send_raw(&app, wrong).await.0, StatusCode::UNAUTHORIZED);
+        let mut authorized = request(Method::POST, "/api/color", json!({ "color": "red" }));
+        authorized.headers_mut().insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert_eq!(send_raw(&app, authorized).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "docs")]
+    async fn openapi_document_is_served_with_a_local_swagger_ui() {
+        let app = test_app();
+        let (status, document) = send(&app, Method::GET, "/api/openapi.json", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(document["paths"].get("/api/color").is_some(), "{document}");
+
+        let (status, headers, page) = send_raw(&app, request(Method::GET, "/swagger/", Value::Null)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers[CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+        assert!(!page.contains("https://"), "{page}");
+        let (_, _, script) = send_raw(&app, request(Method::GET, "/swagger/swagger-initializer.js", Value::Null)).await;
+        assert!(script.contains("/api/openapi.json"), "{script}");
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_echoed() {
+        let mut tagged = request(Method::GET, "/api/health", Value::Null);
+        tagged.headers_mut().insert("x-request-id", "abc-123".parse().unwrap());
+        let (_, headers, _) = send_raw(&test_app(), tagged).await;
+        assert_eq!(headers["x-request-id"], "abc-123");
+
+        let (_, headers, _) = send_raw(&test_app(), request(Method::GET, "/api/health", Value::Null)).await;
+        assert!(!headers["x-request-id"].is_empty());
+    }
+
+    #[tokio::test]
+    async fn cmyk_components_stand_in_for_a_name() {
+        let app = test_app();
+        let cmyk = json!({ "cmyk": [0.0, 1.0, 1.0, 0.0] });
+        assert_eq!(send(&app, Method::POST, "/api/preview", cmyk).await.1["color"], "#FF0000");
+        let cmyk = json!({ "cmyk": [0.0, 1.5, 1.0, 0.0] });
+        assert_eq!(send(&app, Method::POST, "/api/preview", cmyk).await.1["code"], "INVALID_CMYK");
+    }
+
+    #[tokio::test]
+    async fn hsl_values_stand_in_for_a_name() {
+        let app = test_app();
+        let hsl = json!({ "hsl": [0, 100, 50] });
+        assert_eq!(send(&app, Method::POST, "/api/preview", hsl).await.1["color"], "#FF0000");
+        let hsl = json!({ "hsl": [360, 100, 50] });
+        assert_eq!(send(&app, Method::POST, "/api/preview", hsl).await.1["code"], "INVALID_HSL");
+    }
+
+    #[tokio::test]
+    async fn delta_e_endpoint_compares_two_colors() {
+        let delta = json!({ "reference": "red", "sample": "red" });
+        assert_eq!(send(&test_app(), Method::POST, "/api/deltae", delta).await.1["delta_e"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn kelvin_stands_in_for_a_name() {
+        let (_, body) = send(&test_app(), Method::POST, "/api/preview", json!({ "kelvin": 2000 })).await;
+        assert_eq!(body["temperature"], "warm");
+    }
+
+    #[tokio::test]
+    async fn seeded_random_colors_repeat() {
+        let app = test_app();
+        let (_, first) = send(&app, Method::GET, "/api/random?seed=42", Value::Null).await;
+        assert_eq!(send(&app, Method::GET, "/api/random?seed=42", Value::Null).await.1, first);
+
+        send(&app, Method::POST, "/api/random?count=5&seed=42", Value::Null).await;
+        assert_eq!(send(&app, Method::GET, "/api/count", Value::Null).await.1["total"], 5);
+        let (status, body) = send(&app, Method::POST, "/api/random?count=0", Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    #[tokio::test]
+    async fn family_delete_reports_the_units_removed() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "blue", "shade": "light", "quantity": 2 })).await;
+        let (_, body) = send(&app, Method::DELETE, "/api/colors/family/blue", Value::Null).await;
+        assert_eq!(body, json!({ "removed": 2 }));
+        let (_, body) = send(&app, Method::DELETE, "/api/colors/family/purple", Value::Null).await;
+        assert_eq!(body["code"], "UNSUPPORTED_COLOR");
+    }
+
+    #[tokio::test]
+    async fn composition_reports_each_share() {
+        let app = test_app();
+        let batch = json!([{ "color": "yellow", "quantity": 2 }, { "color": "blue" }, { "color": "teal" }]);
+        send(&app, Method::POST, "/api/colors/batch", batch).await;
+        let (_, composition) = send(&app, Method::GET, "/api/color/composition", Value::Null).await;
+        assert_eq!(composition, json!({
+            "yellow_pct": 50.0, "blue_pct": 25.0, "red_pct": 0.0, "green_pct": 0.0, "other_pct": 25.0,
+        }));
+    }
+
+    #[tokio::test]
+    async fn unchanged_color_is_not_modified() {
+        let app = test_app();
+        send(&app, Method::POST, "/api/color", json!({ "color": "yellow" })).await;
+        let (status, headers, _) = send_raw(&app, request(Method::GET, "/api/color", Value::Null)).await;
+        assert_eq!(status, StatusCode::OK);
+        let etag = headers[ETAG].clone();
+        assert_eq!(etag, "\"FFED00FF-json\"");
+
+        let conditional = || {
+            let mut request = request(Method::GET, "/api/color", Value::Null);
+            request.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+            request
+        };
+        let (status, _, body) = send_raw(&app, conditional()).await;
+        assert_eq!((status, body.as_str()), (StatusCode::NOT_MODIFIED, ""));
+        send(&app, Method::POST, "/api/color", json!({ "color": "blue" })).await;
+        assert_eq!(send_raw(&app, conditional()).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_compressed() {
+        let mut compressed = request(Method::GET, "/index.html", Value::Null);
+        compressed.headers_mut().insert(ACCEPT_ENCODING, "gzip".parse().unwrap());
+        let (status, headers, _) = send_raw(&test_app(), compressed).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[CONTENT_ENCODING], "gzip");
+    }
+
+    #[tokio::test]
+    async fn per_color_limit_rejects_adds() {
+        let app = app_with(with_default_session(AppState { max_per_color: Some(3), ..empty_state() }));
+        let red = |quantity: u32| json!({ "color": "red", "quantity": quantity });
+        assert_eq!(send(&app, Method::POST, "/api/color", red(4)).await.1["code"], "COLOR_QUANTITY_EXCEEDED");
+        assert_eq!(send(&app, Method::PATCH, "/api/color", red(4)).await.1["code"], "COLOR_QUANTITY_EXCEEDED");
+        assert_eq!(send(&app, Method::POST, "/api/color", red(3)).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn distinct_colors_are_listed_once() {
+        let app = test_app();
+        let batch = json!([{ "color": "yellow", "quantity": 3 }, { "color": "blue" }]);
+        send(&app, Method::POST, "/api/colors/batch", batch).await;
+        let (_, body) = send(&app, Method::GET, "/api/colors/distinct", Value::Null).await;
+        let hexes: Vec<&Value> = body.as_array().unwrap().iter().map(|color| &color["color"]).collect();
+        assert_eq!(hexes, ["#0047AB", "#FFED00"]);
+    }
+}
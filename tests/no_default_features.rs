@@ -0,0 +1,23 @@
+//! Compile check for the color logic on its own. CI runs
+//!
+//! ```bash
+//! cargo test --no-default-features --test no_default_features
+//! ```
+//!
+//! which builds the library without the `server`, `persistence` and `random` features, and so
+//! without axum, tokio, serde_json and rand. This target only uses what stays available then.
+
+use color_mixer::{mix_counts, Color, ColorMixer, MixMode, Shade};
+
+#[test]
+fn colors_mix_without_the_optional_features() {
+    let mut mixer = ColorMixer::new();
+    mixer.add_colors_str("yellow", Shade::Standard, 2, 255).unwrap();
+    mixer.add_colors_str("blue", Shade::Light, 1, 255).unwrap();
+    mixer.set_mix_mode(MixMode::Additive);
+    assert_eq!(mixer.get_mixed_color().unwrap().to_hex(), "#CCD155");
+
+    let counts = mixer.color_counts();
+    assert_eq!(mix_counts(&counts, MixMode::Additive).unwrap(), mixer.get_mixed_color().unwrap());
+    assert_eq!("navy".parse::<Color>().unwrap().rgb(), (0, 32, 91));
+}